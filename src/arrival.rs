@@ -0,0 +1,179 @@
+use crate::profile::Profile;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// how requests are spread out in time, independent of how fast the target answers
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Arrival {
+    /// evenly spaced, one request every 1 / rate seconds
+    Constant,
+    /// exponentially distributed gaps averaging 1 / rate seconds
+    Poisson,
+    /// requests packed into `on`, then `off` of silence. the rate inside a burst
+    /// is raised so that the average over a cycle is still the requested rate
+    Burst { on: Duration, off: Duration },
+}
+
+impl FromStr for Arrival {
+    type Err = String;
+
+    /// `constant`, `poisson`, `burst` or `burst:<on>/<off>` e.g. `burst:500ms/2s`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "constant" => Ok(Arrival::Constant),
+            "poisson" => Ok(Arrival::Poisson),
+            "burst" => Ok(Arrival::Burst {
+                on: Duration::from_secs(1),
+                off: Duration::from_secs(4),
+            }),
+            _ => {
+                let periods = s.strip_prefix("burst:").ok_or_else(|| {
+                    format!(
+                        "unknown arrival {:?}, expected constant, poisson or burst[:<on>/<off>]",
+                        s
+                    )
+                })?;
+                let (on, off) = periods
+                    .split_once('/')
+                    .ok_or_else(|| format!("expected burst:<on>/<off>, got {:?}", s))?;
+                let on = humantime::parse_duration(on).map_err(|e| e.to_string())?;
+                let off = humantime::parse_duration(off).map_err(|e| e.to_string())?;
+                if on.is_zero() {
+                    return Err("burst on period has to be longer than 0".to_string());
+                }
+                Ok(Arrival::Burst { on, off })
+            }
+        }
+    }
+}
+
+/// hands out the send time of every request, as an offset from the start of the test
+pub struct Schedule {
+    arrival: Arrival,
+    profile: Arc<Profile>,
+    rng: StdRng,
+    // seconds since the start at which the last request was due, before any bursting
+    last_at: f64,
+}
+
+impl Schedule {
+    /// the same seed always produces the same schedule
+    pub fn new(arrival: Arrival, profile: Arc<Profile>, seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Schedule {
+            arrival,
+            profile,
+            rng,
+            last_at: 0.0,
+        }
+    }
+
+    /// offset of the next request, None once the profile has run out
+    pub fn next(&mut self) -> Option<Duration> {
+        // how many requests worth of rate to move forward by, the profile then
+        // turns that into time so changes in the rate are followed smoothly
+        let work = match self.arrival {
+            Arrival::Constant | Arrival::Burst { .. } => 1.0,
+            Arrival::Poisson => {
+                // inverse transform sampling of the exponential distribution,
+                // 1 - u so that ln never sees a 0
+                let u: f64 = self.rng.gen();
+                -(1.0 - u).ln()
+            }
+        };
+        let at = self.profile.advance(self.last_at, work)?;
+        self.last_at = at;
+
+        let at = match self.arrival {
+            Arrival::Burst { on, off } => {
+                // squeeze every cycle's worth of evenly spaced requests into its on
+                // period, which keeps the average rate of the cycle intact
+                let (on, off) = (on.as_secs_f64(), off.as_secs_f64());
+                let cycle = on + off;
+                let cycle_start = at - at % cycle;
+                cycle_start + (at - cycle_start) * on / cycle
+            }
+            _ => at,
+        };
+
+        Some(Duration::from_secs_f64(at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(arrival: &str, stages: &str, seed: Option<u64>) -> Vec<f64> {
+        let profile = Arc::new(Profile::parse(stages).unwrap());
+        let mut schedule = Schedule::new(arrival.parse().unwrap(), profile, seed);
+        std::iter::from_fn(|| schedule.next())
+            .map(|at| at.as_secs_f64())
+            .collect()
+    }
+
+    #[test]
+    fn from_str_reads_every_arrival() {
+        assert!(matches!("constant".parse(), Ok(Arrival::Constant)));
+        assert!(matches!("poisson".parse(), Ok(Arrival::Poisson)));
+        assert!(matches!(
+            "burst".parse(),
+            Ok(Arrival::Burst { on, off }) if on == Duration::from_secs(1) && off == Duration::from_secs(4)
+        ));
+        assert!(matches!(
+            "burst:500ms/2s".parse(),
+            Ok(Arrival::Burst { on, off }) if on == Duration::from_millis(500) && off == Duration::from_secs(2)
+        ));
+        for raw in [
+            "uniform",
+            "burst:1s",
+            "burst:0s/1s",
+            "burst:fast/1s",
+            "burst:1s/",
+        ] {
+            assert!(raw.parse::<Arrival>().is_err(), "{:?} was accepted", raw);
+        }
+    }
+
+    #[test]
+    fn constant_spaces_requests_evenly() {
+        let at = schedule("constant", "hold:10:1s", None);
+        assert_eq!(at.len(), 10);
+        for (indx, at) in at.iter().enumerate() {
+            assert!((at - (indx + 1) as f64 / 10.0).abs() < 1e-9, "{}", at);
+        }
+    }
+
+    #[test]
+    fn burst_squeezes_each_cycle_into_its_on_period() {
+        // 5s cycles, 1s on, 10 rps on average
+        let at = schedule("burst:1s/4s", "hold:10:10s", None);
+        assert_eq!(at.len(), 100);
+        for at in &at {
+            let into_cycle = at % 5.0;
+            assert!(
+                !(1.0 + 1e-9..=5.0 - 1e-9).contains(&into_cycle),
+                "{} is outside of a burst",
+                at
+            );
+        }
+        // packed 5 times as tight as the average rate
+        assert!((at[1] - at[0] - 0.02).abs() < 1e-9);
+    }
+
+    #[test]
+    fn poisson_is_the_same_for_the_same_seed() {
+        let at = schedule("poisson", "hold:100:10s", Some(42));
+        assert_eq!(at, schedule("poisson", "hold:100:10s", Some(42)));
+        assert_ne!(at, schedule("poisson", "hold:100:10s", Some(43)));
+        // 1000 on average
+        assert!((900..1100).contains(&at.len()), "{}", at.len());
+        assert!(at.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+}
@@ -0,0 +1,679 @@
+use crate::types::TransportError;
+use hyper::client::connect::{Connected, Connection};
+use hyper::header::{HeaderValue, CONNECTION, PROXY_AUTHORIZATION};
+use hyper::service::Service;
+use hyper::{Body, Request, Response, Uri};
+use hyper_tls::MaybeHttpsStream;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+
+/// connection handling knobs for the http client(s) the workers send requests with
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClientOptions {
+    /// reuse connections between requests, when off every request opens a new connection
+    pub keepalive: bool,
+    /// idle connections kept open per host, None for no limit
+    pub max_idle_per_host: Option<usize>,
+    /// how long an idle connection is kept around before being closed, hyper's
+    /// default when None
+    pub idle_timeout: Option<Duration>,
+    pub tcp_nodelay: bool,
+    pub connect_timeout: Option<Duration>,
+    /// one client (and so one connection pool) per worker instead of one for the whole run
+    pub per_worker: bool,
+    /// redirects followed before the 3xx is taken as the response, 0 to follow none
+    pub max_redirects: usize,
+}
+
+impl ClientOptions {
+    pub fn build(&self) -> Result<HttpClient, Box<dyn Error>> {
+        let proxies = Arc::new(Proxies::from_env()?);
+        let connector = TimedConnector {
+            tls: Arc::new(native_tls::TlsConnector::new()?.into()),
+            tcp_nodelay: self.tcp_nodelay,
+            connect_timeout: self.connect_timeout,
+            proxies: proxies.clone(),
+        };
+
+        let mut builder = hyper::Client::builder();
+        if self.keepalive {
+            if let Some(max) = self.max_idle_per_host {
+                builder.pool_max_idle_per_host(max);
+            }
+            if let Some(timeout) = self.idle_timeout {
+                builder.pool_idle_timeout(timeout);
+            }
+        } else {
+            builder.pool_max_idle_per_host(0);
+        }
+
+        Ok(HttpClient {
+            inner: builder.build(connector),
+            keepalive: self.keepalive,
+            max_redirects: self.max_redirects,
+            proxies,
+        })
+    }
+}
+
+/// the proxies to send requests through, read from the environment like curl and most
+/// http clients do: `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY`, upper or lower case
+#[derive(Debug, Default)]
+pub struct Proxies {
+    http: Option<Proxy>,
+    https: Option<Proxy>,
+    // hosts, and the domains under them, reached directly. `*` for every host
+    no_proxy: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Proxy {
+    host: String,
+    port: u16,
+    /// `Basic ...` when the proxy url has credentials
+    authorization: Option<HeaderValue>,
+}
+
+impl Proxies {
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let var = |name: &str| {
+            env::var(name.to_uppercase())
+                .or_else(|_| env::var(name))
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+        };
+        Ok(Proxies {
+            http: var("http_proxy")
+                .map(|raw| Proxy::parse(&raw))
+                .transpose()?,
+            https: var("https_proxy")
+                .map(|raw| Proxy::parse(&raw))
+                .transpose()?,
+            no_proxy: var("no_proxy")
+                .unwrap_or_default()
+                .split(',')
+                .map(|host| host.trim().trim_start_matches('.').to_lowercase())
+                .filter(|host| !host.is_empty())
+                .collect(),
+        })
+    }
+
+    /// the proxy a request to `dst` goes through, None to connect directly
+    fn for_target(&self, dst: &Uri) -> Option<&Proxy> {
+        let proxy = match dst.scheme_str() {
+            Some("https") => self.https.as_ref(),
+            _ => self.http.as_ref(),
+        }?;
+        let host = dst
+            .host()?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_lowercase();
+        let bypassed = self.no_proxy.iter().any(|entry| {
+            entry == "*"
+                || host == *entry
+                || host
+                    .strip_suffix(entry.as_str())
+                    .is_some_and(|sub| sub.ends_with('.'))
+        });
+        if bypassed {
+            None
+        } else {
+            Some(proxy)
+        }
+    }
+}
+
+impl Proxy {
+    /// `[http://][user:password@]host[:port]`
+    fn parse(raw: &str) -> Result<Self, Box<dyn Error>> {
+        let raw = raw.trim();
+        let url = if raw.contains("://") {
+            url::Url::parse(raw)
+        } else {
+            url::Url::parse(&format!("http://{}", raw))
+        }
+        .map_err(|e| format!("invalid proxy url {:?}: {}", raw, e))?;
+        if url.scheme() != "http" {
+            return Err(format!("only http proxies are supported, got {:?}", raw).into());
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| format!("proxy url {:?} has no host", raw))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+
+        let authorization = if url.username().is_empty() {
+            None
+        } else {
+            let decode = |part: &str| {
+                percent_encoding::percent_decode_str(part)
+                    .decode_utf8_lossy()
+                    .into_owned()
+            };
+            let credentials = format!(
+                "{}:{}",
+                decode(url.username()),
+                decode(url.password().unwrap_or_default())
+            );
+            let encoded =
+                base64::Engine::encode(&base64::engine::general_purpose::STANDARD, credentials);
+            Some(HeaderValue::from_str(&format!("Basic {}", encoded))?)
+        };
+
+        Ok(Proxy {
+            host,
+            port: url.port().unwrap_or(80),
+            authorization,
+        })
+    }
+}
+
+/// cloning it shares the connection pool
+#[derive(Clone)]
+pub struct HttpClient {
+    inner: hyper::Client<TimedConnector, Body>,
+    keepalive: bool,
+    pub max_redirects: usize,
+    proxies: Arc<Proxies>,
+}
+
+impl HttpClient {
+    pub async fn send(&self, mut req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+        if !self.keepalive {
+            // nothing goes back into the pool, and the target is told to hang up too
+            req.headers_mut()
+                .insert(CONNECTION, HeaderValue::from_static("close"));
+        }
+        // a plain http request is sent to the proxy as it is, https ones get theirs
+        // when the tunnel is opened
+        if req.uri().scheme_str() != Some("https") {
+            let proxy = self.proxies.for_target(req.uri());
+            if let Some(authorization) = proxy.and_then(|proxy| proxy.authorization.clone()) {
+                req.headers_mut().insert(PROXY_AUTHORIZATION, authorization);
+            }
+        }
+        self.inner.request(req).await
+    }
+}
+
+/// how long it took to set up a connection and how much went over it. hyper puts a
+/// copy on every response sent over that connection
+#[derive(Clone)]
+pub struct ConnectionInfo {
+    dns: Duration,
+    connect: Duration,
+    tls: Duration,
+    claimed: Arc<AtomicBool>,
+    exchanges: Arc<Mutex<Exchanges>>,
+}
+
+/// exchanges further back than this are forgotten, a request looks its own up as soon
+/// as its response is read so only a stalled one misses out
+const EXCHANGES_KEPT: usize = 16;
+
+/// plaintext bytes through a connection, so http headers and bodies but not tls framing,
+/// split up by exchange: a request and its response. http/1 runs one at a time per
+/// connection, so a write after a read starts the next one
+#[derive(Default)]
+struct Exchanges {
+    // oldest first, the last one is in progress
+    recent: VecDeque<Exchange>,
+    next_id: u64,
+    // something was read since the last write
+    reading: bool,
+}
+
+struct Exchange {
+    id: u64,
+    written: u64,
+    read: u64,
+}
+
+impl Exchanges {
+    fn wrote(&mut self, bytes: u64) {
+        if bytes == 0 {
+            return;
+        }
+        if self.reading || self.recent.is_empty() {
+            self.start();
+        }
+        if let Some(current) = self.recent.back_mut() {
+            current.written += bytes;
+        }
+    }
+
+    fn read(&mut self, bytes: u64) {
+        if bytes == 0 {
+            return;
+        }
+        if self.recent.is_empty() {
+            self.start();
+        }
+        if let Some(current) = self.recent.back_mut() {
+            current.read += bytes;
+        }
+        self.reading = true;
+    }
+
+    fn start(&mut self) {
+        if self.recent.len() == EXCHANGES_KEPT {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(Exchange {
+            id: self.next_id,
+            written: 0,
+            read: 0,
+        });
+        self.next_id += 1;
+        self.reading = false;
+    }
+}
+
+impl ConnectionInfo {
+    /// (dns, connect, tls) for the request that opened the connection, None on reuse
+    pub fn claim_setup(&self) -> Option<(Duration, Duration, Duration)> {
+        if self.claimed.swap(true, Ordering::Relaxed) {
+            None
+        } else {
+            Some((self.dns, self.connect, self.tls))
+        }
+    }
+
+    /// the exchange a response that just came in belongs to, the latest one anything was
+    /// read in. hyper may already have handed the connection to the next request, but
+    /// that one can't have been answered yet
+    pub fn exchange(&self) -> Option<u64> {
+        let exchanges = self.exchanges.lock().unwrap();
+        exchanges
+            .recent
+            .iter()
+            .rev()
+            .find(|exchange| exchange.read > 0)
+            .map(|exchange| exchange.id)
+    }
+
+    /// (sent, received) bytes of an exchange, complete once its response is fully read
+    /// whatever went over the connection after it
+    pub fn bytes(&self, exchange: u64) -> (u64, u64) {
+        let exchanges = self.exchanges.lock().unwrap();
+        exchanges
+            .recent
+            .iter()
+            .find(|e| e.id == exchange)
+            .map_or((0, 0), |e| (e.written, e.read))
+    }
+}
+
+/// what failed while opening a connection
+#[derive(Debug)]
+pub struct ConnectError {
+    kind: TransportError,
+    cause: Box<dyn Error + Send + Sync>,
+}
+
+impl ConnectError {
+    fn new(kind: TransportError, cause: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        ConnectError {
+            kind,
+            cause: cause.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} error: {}", self.kind, self.cause)
+    }
+}
+
+impl Error for ConnectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.cause)
+    }
+}
+
+/// opens connections the same way hyper's HttpsConnector does, one step at a
+/// time so that dns, tcp connect and the tls handshake can be timed separately.
+/// through a proxy dns and connect are those of the proxy, connect includes opening
+/// the tunnel for https, and tls is still the handshake with the target
+#[derive(Clone)]
+pub struct TimedConnector {
+    tls: Arc<tokio_native_tls::TlsConnector>,
+    tcp_nodelay: bool,
+    connect_timeout: Option<Duration>,
+    proxies: Arc<Proxies>,
+}
+
+impl TimedConnector {
+    async fn connect(self, dst: Uri) -> Result<TimedStream, ConnectError> {
+        let is_https = dst.scheme_str() == Some("https");
+        let host = dst
+            .host()
+            .ok_or_else(|| ConnectError::new(TransportError::Other, "url has no host"))?
+            // ipv6 literals come bracketed in urls
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = dst.port_u16().unwrap_or(if is_https { 443 } else { 80 });
+        let proxy = self.proxies.for_target(&dst);
+        let (connect_host, connect_port) = match proxy {
+            Some(proxy) => (proxy.host.as_str(), proxy.port),
+            None => (host.as_str(), port),
+        };
+
+        let dns_started = Instant::now();
+        let addrs = tokio::net::lookup_host((connect_host, connect_port))
+            .await
+            .map_err(|e| ConnectError::new(TransportError::Dns, e))?
+            .collect::<Vec<_>>();
+        let dns = dns_started.elapsed();
+
+        let connect_started = Instant::now();
+        let mut last_err = io::Error::new(io::ErrorKind::NotFound, "no addresses resolved");
+        let mut tcp = None;
+        for addr in addrs {
+            let attempt = TcpStream::connect(addr);
+            let attempt = match self.connect_timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, attempt).await {
+                    Ok(attempt) => attempt,
+                    Err(elapsed) => {
+                        return Err(ConnectError::new(TransportError::Timeout, elapsed))
+                    }
+                },
+                None => attempt.await,
+            };
+            match attempt {
+                Ok(stream) => {
+                    tcp = Some(stream);
+                    break;
+                }
+                Err(e) => last_err = e,
+            }
+        }
+        let mut tcp = tcp.ok_or_else(|| ConnectError::new(io_error_kind(&last_err), last_err))?;
+        tcp.set_nodelay(self.tcp_nodelay)
+            .map_err(|e| ConnectError::new(TransportError::Other, e))?;
+        if let (Some(proxy), true) = (proxy, is_https) {
+            tunnel(&mut tcp, &host, port, proxy).await?;
+        }
+        let connect = connect_started.elapsed();
+
+        let tls_started = Instant::now();
+        let (stream, tls) = if is_https {
+            let stream = self
+                .tls
+                .connect(&host, tcp)
+                .await
+                .map_err(|e| ConnectError::new(TransportError::Tls, e))?;
+            (MaybeHttpsStream::Https(stream), tls_started.elapsed())
+        } else {
+            (MaybeHttpsStream::Http(tcp), Duration::ZERO)
+        };
+
+        Ok(TimedStream {
+            inner: stream,
+            // hyper then writes the whole url in the request line, for the proxy
+            via_proxy: proxy.is_some() && !is_https,
+            info: ConnectionInfo {
+                dns,
+                connect,
+                tls,
+                claimed: Arc::new(AtomicBool::new(false)),
+                exchanges: Arc::new(Mutex::new(Exchanges::default())),
+            },
+        })
+    }
+}
+
+/// asks the proxy for a tunnel to `host:port` over `tcp`, for the tls handshake to
+/// go through to the target
+async fn tunnel(
+    tcp: &mut TcpStream,
+    host: &str,
+    port: u16,
+    proxy: &Proxy,
+) -> Result<(), ConnectError> {
+    let io_err = |e: io::Error| ConnectError::new(io_error_kind(&e), e);
+    let authority = if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+    let mut connect = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority).into_bytes();
+    if let Some(authorization) = &proxy.authorization {
+        connect.extend_from_slice(b"Proxy-Authorization: ");
+        connect.extend_from_slice(authorization.as_bytes());
+        connect.extend_from_slice(b"\r\n");
+    }
+    connect.extend_from_slice(b"\r\n");
+    tcp.write_all(&connect).await.map_err(io_err)?;
+
+    // nothing comes after the proxy's answer until the tls handshake starts
+    let mut response = Vec::new();
+    let mut buf = [0; 1024];
+    while !response.windows(4).any(|w| w == b"\r\n\r\n") {
+        if response.len() > 16 * 1024 {
+            return Err(ConnectError::new(
+                TransportError::Other,
+                "proxy sent an endless CONNECT response",
+            ));
+        }
+        let read = tcp.read(&mut buf).await.map_err(io_err)?;
+        if read == 0 {
+            return Err(ConnectError::new(
+                TransportError::ConnectionReset,
+                "proxy hung up before the tunnel was open",
+            ));
+        }
+        response.extend_from_slice(&buf[..read]);
+    }
+
+    let status_line =
+        String::from_utf8_lossy(response.split(|&b| b == b'\r').next().unwrap_or_default())
+            .into_owned();
+    if status_line.split_whitespace().nth(1) == Some("200") {
+        Ok(())
+    } else {
+        Err(ConnectError::new(
+            TransportError::Other,
+            format!("proxy refused the tunnel to {}: {}", authority, status_line),
+        ))
+    }
+}
+
+impl Service<Uri> for TimedConnector {
+    type Response = TimedStream;
+    type Error = ConnectError;
+    type Future = Pin<Box<dyn Future<Output = Result<TimedStream, ConnectError>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        Box::pin(self.clone().connect(dst))
+    }
+}
+
+/// a connection that counts what goes through it and hands that and its setup
+/// timings to hyper
+pub struct TimedStream {
+    inner: MaybeHttpsStream<TcpStream>,
+    via_proxy: bool,
+    info: ConnectionInfo,
+}
+
+impl Connection for TimedStream {
+    fn connected(&self) -> Connected {
+        self.inner
+            .connected()
+            .proxy(self.via_proxy)
+            .extra(self.info.clone())
+    }
+}
+
+impl AsyncRead for TimedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled_before = buf.filled().len();
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read = (buf.filled().len() - filled_before) as u64;
+        self.info.exchanges.lock().unwrap().read(read);
+        res
+    }
+}
+
+impl AsyncWrite for TimedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let res = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = res {
+            self.info.exchanges.lock().unwrap().wrote(written as u64);
+        }
+        res
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+fn io_error_kind(err: &io::Error) -> TransportError {
+    match err.kind() {
+        io::ErrorKind::ConnectionRefused => TransportError::ConnectionRefused,
+        io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::BrokenPipe
+        | io::ErrorKind::UnexpectedEof => TransportError::ConnectionReset,
+        io::ErrorKind::TimedOut => TransportError::Timeout,
+        _ => TransportError::Other,
+    }
+}
+
+/// works out which layer a request died in from hyper's error
+pub fn classify(err: &hyper::Error) -> TransportError {
+    if err.is_incomplete_message() {
+        // the target hung up before sending a whole response
+        return TransportError::ConnectionReset;
+    }
+
+    let mut source: Option<&(dyn Error + 'static)> = Some(err);
+    while let Some(e) = source {
+        if let Some(connect_err) = e.downcast_ref::<ConnectError>() {
+            return connect_err.kind;
+        }
+        if let Some(io_err) = e.downcast_ref::<io::Error>() {
+            return io_error_kind(io_err);
+        }
+        source = e.source();
+    }
+
+    TransportError::Other
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> ConnectionInfo {
+        ConnectionInfo {
+            dns: Duration::ZERO,
+            connect: Duration::ZERO,
+            tls: Duration::ZERO,
+            claimed: Arc::new(AtomicBool::new(false)),
+            exchanges: Arc::new(Mutex::new(Exchanges::default())),
+        }
+    }
+
+    #[test]
+    fn bytes_stay_with_their_exchange() {
+        let info = info();
+        let mut exchanges = info.exchanges.lock().unwrap();
+        exchanges.wrote(100);
+        exchanges.wrote(20);
+        exchanges.read(300);
+        drop(exchanges);
+        let first = info.exchange().unwrap();
+
+        // the next request goes out before the first one looks at its bytes
+        info.exchanges.lock().unwrap().wrote(90);
+        assert_eq!(info.exchange(), Some(first));
+        info.exchanges.lock().unwrap().read(50);
+        let second = info.exchange().unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(info.bytes(first), (120, 300));
+        assert_eq!(info.bytes(second), (90, 50));
+    }
+
+    #[test]
+    fn proxy_urls_take_credentials_and_default_to_http() {
+        let proxy = Proxy::parse("proxy.internal:3128").unwrap();
+        assert_eq!((proxy.host.as_str(), proxy.port), ("proxy.internal", 3128));
+        assert!(proxy.authorization.is_none());
+
+        let proxy = Proxy::parse("http://u:p%40ss@[::1]").unwrap();
+        assert_eq!((proxy.host.as_str(), proxy.port), ("::1", 80));
+        assert_eq!(proxy.authorization.unwrap(), "Basic dTpwQHNz");
+
+        assert!(Proxy::parse("socks5://proxy.internal:1080").is_err());
+        assert!(Proxy::parse("http://").is_err());
+    }
+
+    #[test]
+    fn no_proxy_skips_hosts_and_their_subdomains() {
+        let proxies = Proxies {
+            http: Some(Proxy::parse("proxy.internal:3128").unwrap()),
+            https: None,
+            no_proxy: vec!["example.com".to_string(), "10.0.0.1".to_string()],
+        };
+        let via_proxy = |url: &str| proxies.for_target(&url.parse().unwrap()).is_some();
+        assert!(via_proxy("http://target.org/"));
+        assert!(!via_proxy("http://example.com/"));
+        assert!(!via_proxy("http://api.EXAMPLE.com:8080/"));
+        assert!(via_proxy("http://notexample.com/"));
+        assert!(!via_proxy("http://10.0.0.1/"));
+        // no https proxy set
+        assert!(!via_proxy("https://target.org/"));
+    }
+
+    #[test]
+    fn old_exchanges_are_forgotten() {
+        let info = info();
+        let mut exchanges = info.exchanges.lock().unwrap();
+        exchanges.wrote(1);
+        exchanges.read(1);
+        drop(exchanges);
+        let first = info.exchange().unwrap();
+        for _ in 0..EXCHANGES_KEPT {
+            let mut exchanges = info.exchanges.lock().unwrap();
+            exchanges.wrote(1);
+            exchanges.read(1);
+        }
+        assert_eq!(info.bytes(first), (0, 0));
+    }
+}
@@ -0,0 +1,247 @@
+use crate::histogram::LatencyHistogram;
+use crate::slo::{Apdex, ErrorBudget};
+use crate::window::Timeline;
+use crate::{Report, RunResults, StatsConfig, StatusCodes, Throughput};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// everything added up over the measured part of the run, or over the requests in an
+/// agent's snapshot
+#[derive(Serialize, Deserialize)]
+pub struct Totals {
+    /// coordinated omission corrected latencies, measured from when a request was scheduled
+    pub durations: LatencyHistogram,
+    /// latencies measured from when a request actually started, only kept if asked for
+    pub uncorrected_durations: LatencyHistogram,
+    pub status_codes: StatusCodes,
+    /// distribution over the requests that went through it, per request phase in
+    /// `Phases::named` order
+    pub phase_durations: [LatencyHistogram; 5],
+    /// running total over all requests, per request phase
+    pub phase_totals: [Duration; 5],
+    pub apdex: Option<Apdex>,
+}
+
+impl Totals {
+    pub fn new(stats: &StatsConfig) -> Self {
+        let histogram = || {
+            LatencyHistogram::new(stats.significant_digits)
+                .expect("significant digits are checked when parsing the cli")
+        };
+        Totals {
+            durations: histogram(),
+            uncorrected_durations: histogram(),
+            status_codes: StatusCodes::new(),
+            phase_durations: [
+                histogram(),
+                histogram(),
+                histogram(),
+                histogram(),
+                histogram(),
+            ],
+            phase_totals: [Duration::ZERO; 5],
+            apdex: stats.apdex_t.map(Apdex::new),
+        }
+    }
+
+    fn record(&mut self, received: &Report, stats: &StatsConfig) {
+        if let Some(status) = received.status {
+            self.status_codes.record(status);
+        }
+
+        for (indx, (_, phase)) in received.phases.named().iter().enumerate() {
+            // a reused connection skips dns, connect and tls altogether
+            if !phase.is_zero() {
+                self.phase_durations[indx].record(*phase);
+            }
+            self.phase_totals[indx] += *phase;
+        }
+
+        self.durations.record(received.corrected_duration);
+        if let Some(apdex) = self.apdex.as_mut() {
+            apdex.record(received.corrected_duration, received.succeeded > 0);
+        }
+        if stats.show_uncorrected {
+            self.uncorrected_durations.record(received.duration);
+        }
+    }
+
+    fn merge(&mut self, other: &Totals) {
+        self.durations.merge(&other.durations);
+        self.uncorrected_durations
+            .merge(&other.uncorrected_durations);
+        self.status_codes.merge(&other.status_codes);
+        for (phase, other) in self.phase_durations.iter_mut().zip(&other.phase_durations) {
+            phase.merge(other);
+        }
+        for (total, other) in self.phase_totals.iter_mut().zip(&other.phase_totals) {
+            *total += *other;
+        }
+        if let (Some(apdex), Some(other)) = (self.apdex.as_mut(), other.apdex.as_ref()) {
+            apdex.merge(other);
+        }
+    }
+}
+
+/// the requests an agent finished since its previous snapshot, added up. agents send
+/// one of these a few times a second instead of the report of every request
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub report: Report,
+    pub totals: Totals,
+}
+
+impl Snapshot {
+    pub fn new(stats: &StatsConfig) -> Self {
+        Snapshot {
+            report: Report::new(),
+            totals: Totals::new(stats),
+        }
+    }
+
+    pub fn record(&mut self, received: &Report, stats: &StatsConfig) {
+        self.report.add_report(received);
+        self.totals.record(received, stats);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.report.total_requests == 0
+    }
+}
+
+/// what the tower can be fed: the report of a single request from a local worker, or
+/// a snapshot of many from an agent
+pub trait Record {
+    fn record_into(&self, collector: &mut Collector);
+}
+
+impl Record for Arc<Report> {
+    fn record_into(&self, collector: &mut Collector) {
+        collector.record(self);
+    }
+}
+
+impl Record for Box<Snapshot> {
+    fn record_into(&self, collector: &mut Collector) {
+        collector.merge(self);
+    }
+}
+
+/// takes in the report of every request and keeps what the tower shows, whether it
+/// draws the tui or prints plain progress lines
+pub struct Collector {
+    /// every measured report added up
+    pub report: Report,
+    pub totals: Totals,
+    pub throughput: Throughput,
+    /// the run second by second, warm-up included
+    pub timeline: Timeline,
+    /// every request in so far, warm-up or not
+    pub requests_done: u64,
+    // during warm-up the totals fill up as usual so there is something to look at,
+    // they start over once it ends
+    warming_up: bool,
+    started_at: Instant,
+    stats: StatsConfig,
+}
+
+impl Collector {
+    pub fn new(started_at: Instant, stats: &StatsConfig) -> Self {
+        Collector {
+            report: Report::new(),
+            totals: Totals::new(stats),
+            throughput: Throughput::new(),
+            timeline: Timeline::new(
+                stats.window,
+                stats.significant_digits,
+                stats.percentiles.clone(),
+            ),
+            requests_done: 0,
+            warming_up: !stats.warmup.is_zero(),
+            started_at,
+            stats: stats.clone(),
+        }
+    }
+
+    pub fn record(&mut self, received: &Report) {
+        let now = self.started_at.elapsed();
+        self.requests_done += received.total_requests as u64;
+        self.throughput
+            .record(now, received.bytes_sent, received.bytes_received);
+        self.timeline.record(now, received);
+
+        self.end_warmup_at(now);
+        // a request scheduled during warm-up stays out even if it finishes after
+        let scheduled_at = now.saturating_sub(received.corrected_duration);
+        if self.warming_up || scheduled_at >= self.stats.warmup {
+            self.report.add_report(received);
+            self.totals.record(received, &self.stats);
+        }
+    }
+
+    /// adds up an agent's snapshot, as if every request in it finished now. when they
+    /// were scheduled isn't known any more, so after warm-up all of them are measured
+    pub fn merge(&mut self, snapshot: &Snapshot) {
+        let now = self.started_at.elapsed();
+        let received = &snapshot.report;
+        self.requests_done += received.total_requests as u64;
+        self.throughput
+            .record(now, received.bytes_sent, received.bytes_received);
+        self.timeline
+            .merge(now, received, &snapshot.totals.durations);
+
+        self.end_warmup_at(now);
+        self.report.add_report(received);
+        self.totals.merge(&snapshot.totals);
+    }
+
+    fn end_warmup_at(&mut self, now: Duration) {
+        if self.warming_up && now >= self.stats.warmup {
+            self.warming_up = false;
+            self.report = Report::new();
+            self.totals = Totals::new(&self.stats);
+        }
+    }
+
+    /// the totals, and so the overall rates, start over after warm-up
+    pub fn measured_from(&self, now: Duration) -> Duration {
+        if now >= self.stats.warmup {
+            self.stats.warmup
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    pub fn error_budget(&self) -> Option<ErrorBudget> {
+        self.stats
+            .availability
+            .map(|availability| ErrorBudget::new(availability, &self.report))
+    }
+
+    /// `completed` is false when the run was stopped before every report was in
+    pub fn finish(mut self, completed: bool) -> RunResults {
+        // a run that never got past warm-up has nothing to show for itself
+        if self.warming_up {
+            self.report = Report::new();
+            self.totals = Totals::new(&self.stats);
+        }
+
+        RunResults {
+            error_budget: self.error_budget(),
+            report: self.report,
+            latencies: self.totals.durations,
+            uncorrected_latencies: self
+                .stats
+                .show_uncorrected
+                .then_some(self.totals.uncorrected_durations),
+            apdex: self.totals.apdex,
+            status_codes: self.totals.status_codes,
+            timeline: self.timeline,
+            throughput: self.throughput,
+            warmup: self.stats.warmup,
+            elapsed: self.started_at.elapsed().saturating_sub(self.stats.warmup),
+            completed,
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use crate::histogram::LatencyHistogram;
+use crate::types::{self, format_latency, RunResults};
+use crate::Report;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::time::Duration;
+
+// bumped whenever a saved run changes in a way older versions can't read
+const FORMAT_VERSION: u32 = 1;
+
+// two sided 95%
+const Z_95: f64 = 1.96;
+
+/// what `--save` writes: the full latency distribution and counters of a run, enough
+/// to compare a later run against it
+#[derive(Serialize, Deserialize)]
+pub struct SavedRun {
+    version: u32,
+    report: Report,
+    /// coordinated omission corrected latencies
+    latencies: LatencyHistogram,
+    elapsed: Duration,
+    /// requests finished in every whole second of the run
+    requests_per_second: Vec<u64>,
+}
+
+impl SavedRun {
+    pub fn new(results: &RunResults) -> Self {
+        SavedRun {
+            version: FORMAT_VERSION,
+            report: results.report.clone(),
+            latencies: results.latencies.clone(),
+            elapsed: results.elapsed,
+            requests_per_second: results
+                .timeline
+                .finished_seconds()
+                .iter()
+                .skip(results.warmup.as_secs_f64().ceil() as usize)
+                .map(|second| second.requests)
+                .collect(),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let contents =
+            fs::read(path).map_err(|e| format!("could not read saved run {}: {}", path, e))?;
+        let run: SavedRun = serde_json::from_slice(&contents)
+            .map_err(|e| format!("{} is not a saved run: {}", path, e))?;
+        if run.version != FORMAT_VERSION {
+            return Err(format!(
+                "{} was saved in format {}, this xctl reads format {}",
+                path, run.version, FORMAT_VERSION
+            )
+            .into());
+        }
+        Ok(run)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_vec(self)?)
+            .map_err(|e| format!("could not save the run to {}: {}", path, e))?;
+        Ok(())
+    }
+
+    fn rps(&self) -> f64 {
+        types::rps(&self.report, self.elapsed)
+    }
+
+    fn errors(&self) -> u64 {
+        (self.report.failed + self.report.timed_out) as u64
+    }
+}
+
+/// prints how `candidate` differs from `baseline` at every percentile, in mean latency,
+/// throughput and error rate, and whether each difference is significant at 95% or
+/// could just be noise. requests are assumed to be independent of each other
+pub fn print_comparison(
+    out: &mut impl Write,
+    baseline: &SavedRun,
+    candidate: &SavedRun,
+    percentiles: &[f64],
+) -> io::Result<()> {
+    let (before, after) = (
+        baseline.latencies.summary(&[]),
+        candidate.latencies.summary(&[]),
+    );
+
+    writeln!(
+        out,
+        "{:<12}{:>13}{:>13}{:>11}  verdict",
+        "", "baseline", "candidate", "change"
+    )?;
+    for percentile in percentiles {
+        let (old, new) = (
+            baseline.latencies.percentile(*percentile),
+            candidate.latencies.percentile(*percentile),
+        );
+        // the intervals are only there once both runs measured something
+        let significant = if before.count > 0 && after.count > 0 {
+            let (old_low, old_high) =
+                percentile_interval(&baseline.latencies, before.count, *percentile);
+            let (new_low, new_high) =
+                percentile_interval(&candidate.latencies, after.count, *percentile);
+            Some(new_low > old_high || new_high < old_low)
+        } else {
+            None
+        };
+        print_row(
+            out,
+            &format!("p{}", percentile),
+            format_latency(old),
+            format_latency(new),
+            relative_change(old.as_secs_f64(), new.as_secs_f64()),
+            significant,
+        )?;
+    }
+
+    let (old_mean, new_mean) = (before.mean.as_secs_f64(), after.mean.as_secs_f64());
+    print_row(
+        out,
+        "mean",
+        format_latency(before.mean),
+        format_latency(after.mean),
+        relative_change(old_mean, new_mean),
+        welch(
+            (
+                old_mean,
+                before.stddev.as_secs_f64().powi(2),
+                before.count as f64,
+            ),
+            (
+                new_mean,
+                after.stddev.as_secs_f64().powi(2),
+                after.count as f64,
+            ),
+        ),
+    )?;
+
+    print_row(
+        out,
+        "rps",
+        format!("{:.1}", baseline.rps()),
+        format!("{:.1}", candidate.rps()),
+        relative_change(baseline.rps(), candidate.rps()),
+        welch(
+            moments(&baseline.requests_per_second),
+            moments(&candidate.requests_per_second),
+        ),
+    )?;
+
+    let (old_total, new_total) = (
+        baseline.report.total_requests as u64,
+        candidate.report.total_requests as u64,
+    );
+    let error_rate = |errors: u64, total: u64| {
+        if total > 0 {
+            errors as f64 / total as f64
+        } else {
+            0.0
+        }
+    };
+    let (old_rate, new_rate) = (
+        error_rate(baseline.errors(), old_total),
+        error_rate(candidate.errors(), new_total),
+    );
+    print_row(
+        out,
+        "error rate",
+        format!("{:.3}%", old_rate * 100.0),
+        format!("{:.3}%", new_rate * 100.0),
+        format!("{:+.3}pp", (new_rate - old_rate) * 100.0),
+        proportions_differ(
+            (baseline.errors(), old_total),
+            (candidate.errors(), new_total),
+        ),
+    )?;
+    Ok(())
+}
+
+fn print_row(
+    out: &mut impl Write,
+    name: &str,
+    old: String,
+    new: String,
+    change: String,
+    significant: Option<bool>,
+) -> io::Result<()> {
+    let verdict = match significant {
+        Some(true) => "significant",
+        Some(false) => "noise",
+        None => "n/a, too few samples",
+    };
+    writeln!(
+        out,
+        "{:<12}{:>13}{:>13}{:>11}  {}",
+        name, old, new, change, verdict
+    )
+}
+
+fn relative_change(old: f64, new: f64) -> String {
+    if old > 0.0 {
+        format!("{:+.1}%", (new - old) / old * 100.0)
+    } else {
+        "n/a".to_string()
+    }
+}
+
+/// 95% confidence interval of the latency at `percentile`, from the order statistics
+/// around its rank. it holds whatever the shape of the distribution
+fn percentile_interval(
+    latencies: &LatencyHistogram,
+    count: u64,
+    percentile: f64,
+) -> (Duration, Duration) {
+    let q = percentile / 100.0;
+    let spread = Z_95 * (q * (1.0 - q) / count as f64).sqrt();
+    (
+        latencies.percentile(((q - spread) * 100.0).max(0.0)),
+        latencies.percentile(((q + spread) * 100.0).min(100.0)),
+    )
+}
+
+/// (mean, sample variance, count) of a series
+fn moments(samples: &[u64]) -> (f64, f64, f64) {
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<u64>() as f64 / n.max(1.0);
+    let variance = samples
+        .iter()
+        .map(|&sample| (sample as f64 - mean).powi(2))
+        .sum::<f64>()
+        / (n - 1.0).max(1.0);
+    (mean, variance, n)
+}
+
+/// welch's t-test on two (mean, variance, count), Some(true) when the means differ
+/// at 95%. None with fewer than two samples on either side
+fn welch(a: (f64, f64, f64), b: (f64, f64, f64)) -> Option<bool> {
+    let ((mean_a, var_a, n_a), (mean_b, var_b, n_b)) = (a, b);
+    if n_a < 2.0 || n_b < 2.0 {
+        return None;
+    }
+    let (se_a, se_b) = (var_a / n_a, var_b / n_b);
+    if se_a + se_b == 0.0 {
+        // no spread at all, any difference is real
+        return Some(mean_a != mean_b);
+    }
+    let t = (mean_b - mean_a) / (se_a + se_b).sqrt();
+    // welch-satterthwaite degrees of freedom
+    let df = (se_a + se_b).powi(2) / (se_a.powi(2) / (n_a - 1.0) + se_b.powi(2) / (n_b - 1.0));
+    Some(t.abs() > t_critical(df))
+}
+
+/// two sided 95% critical value of student's t with `df` degrees of freedom
+fn t_critical(df: f64) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    match df.floor() as usize {
+        0 => TABLE[0],
+        df @ 1..=30 => TABLE[df - 1],
+        31..=60 => 2.0,
+        61..=120 => 1.98,
+        _ => Z_95,
+    }
+}
+
+/// two proportion z-test on (hits, total), Some(true) when the rates differ at 95%
+fn proportions_differ(a: (u64, u64), b: (u64, u64)) -> Option<bool> {
+    let ((hits_a, n_a), (hits_b, n_b)) = (a, b);
+    if n_a == 0 || n_b == 0 {
+        return None;
+    }
+    let (n_a, n_b) = (n_a as f64, n_b as f64);
+    let pooled = (hits_a + hits_b) as f64 / (n_a + n_b);
+    let se = (pooled * (1.0 - pooled) * (1.0 / n_a + 1.0 / n_b)).sqrt();
+    if se == 0.0 {
+        // both all errors or both error free
+        return Some(false);
+    }
+    let z = (hits_b as f64 / n_b - hits_a as f64 / n_a) / se;
+    Some(z.abs() > Z_95)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_critical_follows_the_table_then_tends_to_z() {
+        assert_eq!(t_critical(0.4), 12.706);
+        assert_eq!(t_critical(1.0), 12.706);
+        assert_eq!(t_critical(9.7), 2.262);
+        assert_eq!(t_critical(30.0), 2.042);
+        assert_eq!(t_critical(45.0), 2.0);
+        assert_eq!(t_critical(100.0), 1.98);
+        assert_eq!(t_critical(5000.0), Z_95);
+    }
+
+    #[test]
+    fn moments_use_the_sample_variance() {
+        assert_eq!(moments(&[1, 2, 3]), (2.0, 1.0, 3.0));
+        assert_eq!(moments(&[]), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn welch_tells_a_real_shift_from_noise() {
+        assert_eq!(welch((10.0, 1.0, 30.0), (10.2, 1.0, 30.0)), Some(false));
+        assert_eq!(welch((10.0, 1.0, 30.0), (11.0, 1.0, 30.0)), Some(true));
+        // the same shift is noise with a wide enough spread
+        assert_eq!(welch((10.0, 25.0, 30.0), (11.0, 25.0, 30.0)), Some(false));
+    }
+
+    #[test]
+    fn welch_edge_cases() {
+        assert_eq!(welch((10.0, 1.0, 1.0), (11.0, 1.0, 30.0)), None);
+        assert_eq!(welch((10.0, 0.0, 5.0), (10.0, 0.0, 5.0)), Some(false));
+        assert_eq!(welch((10.0, 0.0, 5.0), (10.5, 0.0, 5.0)), Some(true));
+    }
+
+    #[test]
+    fn proportions_differ_at_95_percent() {
+        assert_eq!(proportions_differ((10, 1000), (30, 1000)), Some(true));
+        assert_eq!(proportions_differ((10, 1000), (12, 1000)), Some(false));
+        assert_eq!(proportions_differ((0, 100), (0, 200)), Some(false));
+        assert_eq!(proportions_differ((100, 100), (200, 200)), Some(false));
+        assert_eq!(proportions_differ((0, 0), (1, 10)), None);
+    }
+}
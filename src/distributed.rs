@@ -0,0 +1,428 @@
+use crate::collector::Snapshot;
+use crate::{
+    conclude, generate_load, spawn_tower, LoadConfig, RunResults, StatsConfig, TestConfig,
+    TestLength,
+};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// how often an agent sends what its requests came to, often enough for the tui to
+/// keep moving without the controller link carrying a message per request
+const SNAPSHOT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// everything a controller and an agent say to each other, one json object per line
+#[derive(Serialize, Deserialize)]
+enum Message {
+    /// controller to agent: the agent's share of the load test, how to add up its
+    /// snapshots, and the token the agent was started with to prove the controller may
+    /// hand it one
+    Config {
+        token: String,
+        load: Box<LoadConfig>,
+        stats: Box<StatsConfig>,
+    },
+    /// agent to controller: config taken, waiting for Start
+    Ready,
+    /// agent to controller: the config can't be run, and why
+    Rejected(String),
+    /// controller to agent: send the first request now
+    Start,
+    /// agent to controller: the requests finished since the previous snapshot
+    Snapshot(Box<Snapshot>),
+    /// agent to controller: every request has been sent and reported
+    Done,
+}
+
+/// one end of a controller <-> agent connection
+struct Peer {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: BufWriter<OwnedWriteHalf>,
+}
+
+impl Peer {
+    fn new(stream: TcpStream) -> Self {
+        let (reader, writer) = stream.into_split();
+        Peer {
+            lines: BufReader::new(reader).lines(),
+            writer: BufWriter::new(writer),
+        }
+    }
+
+    /// None once the other end hung up
+    async fn recv(&mut self) -> Result<Option<Message>, BoxError> {
+        match self.lines.next_line().await? {
+            Some(line) => Ok(Some(serde_json::from_str(&line)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// buffers a message, it only goes out on the next `flush` or `send`
+    async fn queue(&mut self, msg: &Message) -> Result<(), BoxError> {
+        let mut line = serde_json::to_vec(msg)?;
+        line.push(b'\n');
+        self.writer.write_all(&line).await?;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), BoxError> {
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    async fn send(&mut self, msg: &Message) -> Result<(), BoxError> {
+        self.queue(msg).await?;
+        self.flush().await
+    }
+}
+
+/// what an agent takes from the controllers that connect to it
+pub struct AgentOptions {
+    /// shared with the controllers, anyone else is turned away
+    pub token: String,
+    /// most concurrent clients a controller may ask for, every one is a task and an
+    /// http client of its own
+    pub max_concurrency: u64,
+}
+
+/// accepts controllers on `listen` and runs their load tests, one at a time
+pub async fn agent(listen: &str, options: AgentOptions) -> Result<(), BoxError> {
+    if options.token.is_empty() {
+        return Err("the agent needs a --token for controllers to present".into());
+    }
+    let listener = TcpListener::bind(listen)
+        .await
+        .map_err(|e| format!("could not listen on {}: {}", listen, e))?;
+    println!("agent listening on {}", listener.local_addr()?);
+    serve(listener, &options).await
+}
+
+async fn serve(listener: TcpListener, options: &AgentOptions) -> Result<(), BoxError> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        println!("controller {} connected", addr);
+        // the next controller waits in the backlog until this test is over
+        match run_for(Peer::new(stream), options).await {
+            Ok(()) => println!("test for {} done", addr),
+            Err(e) => eprintln!("test for {} failed: {}", addr, e),
+        }
+    }
+}
+
+/// runs a single load test for a controller, streaming back snapshots of its requests
+async fn run_for(mut controller: Peer, options: &AgentOptions) -> Result<(), BoxError> {
+    let (token, config, stats) = match controller.recv().await {
+        Ok(Some(Message::Config { token, load, stats })) => (token, load, stats),
+        Ok(_) => return Err("controller did not send a test config".into()),
+        Err(e) => {
+            // most likely a config this agent can't make sense of, e.g. a bad url
+            let _ = controller.send(&Message::Rejected(e.to_string())).await;
+            return Err(e);
+        }
+    };
+    if !same_token(&token, &options.token) {
+        let _ = controller
+            .send(&Message::Rejected("wrong token".to_string()))
+            .await;
+        return Err("controller sent the wrong token".into());
+    }
+    if !(1..=options.max_concurrency).contains(&config.concurrent_clients) {
+        let reason = format!(
+            "{} concurrent clients asked for, this agent runs 1 to {}",
+            config.concurrent_clients, options.max_concurrency
+        );
+        let _ = controller.send(&Message::Rejected(reason.clone())).await;
+        return Err(reason.into());
+    }
+    controller.send(&Message::Ready).await?;
+
+    match controller.recv().await? {
+        Some(Message::Start) => {}
+        _ => return Err("controller went away before starting the test".into()),
+    }
+
+    let start = Instant::now();
+    let (sender, mut receiver) = mpsc::channel(100);
+    // the sender goes with the load, so the channel closes when the load is done
+    let load = tokio::spawn(generate_load(*config, sender, None, start));
+
+    let mut snapshot = Snapshot::new(&stats);
+    let mut send_snapshot = tokio::time::interval(SNAPSHOT_INTERVAL);
+    loop {
+        tokio::select! {
+            received = receiver.recv() => match received {
+                Some(report) => snapshot.record(&report, &stats),
+                None => break,
+            },
+            _ = send_snapshot.tick() => {
+                if !snapshot.is_empty() {
+                    let taken = mem::replace(&mut snapshot, Snapshot::new(&stats));
+                    controller.send(&Message::Snapshot(Box::new(taken))).await?;
+                }
+            }
+        }
+    }
+    if !snapshot.is_empty() {
+        controller
+            .queue(&Message::Snapshot(Box::new(snapshot)))
+            .await?;
+    }
+
+    if let Ok(Err(())) = load.await {
+        controller
+            .send(&Message::Rejected("could not start the load".to_string()))
+            .await?;
+        return Err("could not start the load".into());
+    }
+    controller.send(&Message::Done).await
+}
+
+/// hands every agent its share of the load test, starts them together and feeds the
+/// snapshots they stream back into the tui as if the requests had been sent from here
+pub async fn controller(addrs: &[String], token: &str, config: TestConfig) -> Result<(), ()> {
+    // agents only send snapshots back, which don't say when or where a request went
+    if config.request_log.is_some() {
+        eprintln!("--request-log is not supported on a controller, only on a local run");
+        return Err(());
+    }
+    let results = run_agents(addrs, token, &config).await?;
+    conclude(&results, &config)
+}
+
+async fn run_agents(addrs: &[String], token: &str, config: &TestConfig) -> Result<RunResults, ()> {
+    let mut agents = Vec::new();
+    for (indx, addr) in addrs.iter().enumerate() {
+        let stream = TcpStream::connect(addr)
+            .await
+            .map_err(|e| eprintln!("could not connect to agent {}: {}", addr, e))?;
+        let mut agent = Peer::new(stream);
+        agent
+            .send(&Message::Config {
+                token: token.to_string(),
+                load: Box::new(share(&config.load, indx, addrs.len())),
+                stats: Box::new(config.stats.clone()),
+            })
+            .await
+            .map_err(|e| eprintln!("could not send the test to agent {}: {}", addr, e))?;
+        agents.push((addr, agent));
+    }
+
+    // nobody starts until every agent has taken its config
+    for (addr, agent) in &mut agents {
+        match agent.recv().await {
+            Ok(Some(Message::Ready)) => {}
+            Ok(Some(Message::Rejected(reason))) => {
+                eprintln!("agent {} rejected the test: {}", addr, reason);
+                return Err(());
+            }
+            Ok(_) => {
+                eprintln!("agent {} hung up before it was ready", addr);
+                return Err(());
+            }
+            Err(e) => {
+                eprintln!("could not hear back from agent {}: {}", addr, e);
+                return Err(());
+            }
+        }
+    }
+
+    let start = Instant::now();
+    for (addr, agent) in &mut agents {
+        agent
+            .send(&Message::Start)
+            .await
+            .map_err(|e| eprintln!("could not start agent {}: {}", addr, e))?;
+    }
+
+    let (sender, tower) = spawn_tower(
+        start,
+        config.load.test_length,
+        Arc::new(config.load.profile.clone()),
+        config.stats.clone(),
+    );
+
+    let followers = agents
+        .into_iter()
+        .map(|(addr, agent)| tokio::spawn(follow(addr.clone(), agent, sender.clone())))
+        .collect::<Vec<_>>();
+    // the tower is done once every agent is
+    drop(sender);
+
+    let mut results = tower.await.map_err(|e| eprintln!("{}", e))??;
+    for follower in followers {
+        // the tower only finishes early when the run is stopped, the agents don't matter then
+        if !results.completed {
+            follower.abort();
+            continue;
+        }
+        let followed = follower.await.map_err(|e| e.to_string()).and_then(|f| f);
+        if let Err(e) = followed {
+            // the totals are missing whatever this agent didn't get to report
+            eprintln!("{}", e);
+            results.completed = false;
+        }
+    }
+    Ok(results)
+}
+
+/// feeds an agent's snapshots into the tower, Err with what happened if the agent
+/// stops any other way than with Done
+async fn follow(
+    addr: String,
+    mut agent: Peer,
+    tower: mpsc::Sender<Box<Snapshot>>,
+) -> Result<(), String> {
+    loop {
+        match agent.recv().await {
+            Ok(Some(Message::Snapshot(snapshot))) => {
+                if tower.send(snapshot).await.is_err() {
+                    // the run was stopped, nobody is waiting for the rest
+                    return Ok(());
+                }
+            }
+            Ok(Some(Message::Done)) => return Ok(()),
+            Ok(Some(Message::Rejected(reason))) => {
+                return Err(format!("agent {} gave up: {}", addr, reason))
+            }
+            Ok(Some(_)) => return Err(format!("agent {} sent something out of turn", addr)),
+            Ok(None) => return Err(format!("agent {} hung up before it was done", addr)),
+            Err(e) => return Err(format!("lost agent {}: {}", addr, e)),
+        }
+    }
+}
+
+/// compares every byte whatever the first difference, so how long it takes doesn't
+/// give away how much of the token was right
+fn same_token(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// the part of the load test that agent `indx` out of `count` runs
+fn share(load: &LoadConfig, indx: usize, count: usize) -> LoadConfig {
+    let mut share = load.clone();
+    share.profile = load.profile.scaled(1.0 / count as f64);
+    if let TestLength::Requests(n) = load.test_length {
+        // the first n % count agents send one request more than the rest
+        let (indx, count) = (indx as u64, count as u64);
+        share.test_length = TestLength::Requests(n / count + (indx < n % count) as u64);
+    }
+    // agents must not all pick the same targets at the same times
+    share.seed = load.seed.map(|seed| seed.wrapping_add((indx as u64) << 32));
+    share
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{resolve, TestArgs};
+    use structopt::StructOpt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// answers every request on every connection with a 200, returns its url
+    async fn target() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    let mut chunk = [0; 4096];
+                    while let Ok(n @ 1..) = stream.read(&mut chunk).await {
+                        buf.extend_from_slice(&chunk[..n]);
+                        // requests without a body end at the blank line after the headers
+                        while let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                            buf.drain(..end + 4);
+                            let res = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                            if stream.write_all(res).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    async fn spawn_agent(max_concurrency: u64) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let options = AgentOptions {
+            token: "secret".to_string(),
+            max_concurrency,
+        };
+        tokio::spawn(async move { serve(listener, &options).await });
+        addr
+    }
+
+    /// takes a test like an agent would, then hangs up as soon as it has started
+    async fn spawn_dropout() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut controller = Peer::new(stream);
+            controller.recv().await.unwrap();
+            controller.send(&Message::Ready).await.unwrap();
+            controller.recv().await.unwrap();
+        });
+        addr
+    }
+
+    fn test_config(args: &[&str]) -> TestConfig {
+        let args = ["xctl", "--no-tui", "--queries-per-second", "200"]
+            .iter()
+            .chain(args);
+        resolve(TestArgs::from_iter_safe(args).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn controller_adds_up_what_every_agent_sent() {
+        let url = target().await;
+        let agents = vec![spawn_agent(10).await, spawn_agent(10).await];
+        let config = test_config(&["-u", &url, "-n", "25", "-c", "4"]);
+
+        let results = run_agents(&agents, "secret", &config).await.unwrap();
+        assert_eq!(results.report.total_requests, 25);
+        assert_eq!(results.report.succeeded, 25);
+        assert_eq!(results.latencies.summary(&[]).count, 25);
+        assert_eq!(results.report.failed, 0);
+        assert!(results.completed);
+    }
+
+    #[tokio::test]
+    async fn losing_an_agent_leaves_the_run_incomplete() {
+        let url = target().await;
+        let agents = vec![spawn_agent(10).await, spawn_dropout().await];
+        let config = test_config(&["-u", &url, "-n", "20", "-c", "4"]);
+
+        let results = run_agents(&agents, "secret", &config).await.unwrap();
+        assert_eq!(results.report.total_requests, 10);
+        assert!(!results.completed);
+    }
+
+    #[tokio::test]
+    async fn agent_turns_away_a_wrong_token_or_too_many_clients() {
+        let url = target().await;
+        let agents = vec![spawn_agent(10).await];
+
+        let config = test_config(&["-u", &url, "-n", "5", "-c", "2"]);
+        assert!(run_agents(&agents, "guess", &config).await.is_err());
+
+        let config = test_config(&["-u", &url, "-n", "5", "-c", "50"]);
+        assert!(run_agents(&agents, "secret", &config).await.is_err());
+    }
+}
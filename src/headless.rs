@@ -0,0 +1,146 @@
+use crate::collector::{Collector, Record};
+use crate::types::{format_latency, megabytes};
+use crate::{RunResults, StatsConfig, TestLength};
+use std::error::Error;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::Receiver;
+
+/// the tower without the tui, for ci logs, pipes and anything else that isn't a
+/// terminal: one plain progress line every `stats.progress_interval` instead
+pub async fn write_to_stdout<R: Record>(
+    report_receiver: &mut Receiver<R>,
+    test_started_at: Instant,
+    test_length: TestLength,
+    stats: &StatsConfig,
+) -> Result<RunResults, Box<dyn Error>> {
+    let mut collector = Collector::new(test_started_at, stats);
+
+    let mut progress = tokio::time::interval_at(
+        (test_started_at + stats.progress_interval).into(),
+        stats.progress_interval,
+    );
+    // without raw mode ctrl-c is a signal, it still ends the run with what came in so far
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    // set once whoever reads the progress lines, e.g. `| head`, has gone away
+    let mut stdout_closed = false;
+
+    // false when the run is stopped before every report is in
+    let completed = loop {
+        tokio::select! {
+            received = report_receiver.recv() => match received {
+                Some(received) => received.record_into(&mut collector),
+                // every sender is gone, so is the load
+                None => break true,
+            },
+            _ = progress.tick(), if !stdout_closed => {
+                let line = progress_line(&mut collector, test_started_at.elapsed(), test_length, stats);
+                // the run carries on without anyone watching, the summary goes the same way
+                match writeln!(io::stdout().lock(), "{}", line) {
+                    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => stdout_closed = true,
+                    written => written?,
+                }
+            }
+            _ = &mut ctrl_c => break false,
+        }
+    };
+
+    Ok(collector.finish(completed))
+}
+
+/// e.g. `[12s/1m] 598 requests, 0 errors | last 10s: 49.8 rps, 0.00% errors, p50 4.120ms, p99 10.203ms`
+fn progress_line(
+    collector: &mut Collector,
+    now: Duration,
+    test_length: TestLength,
+    stats: &StatsConfig,
+) -> String {
+    let mut at = match test_length {
+        TestLength::Duration(total) => format!(
+            "{}/{}",
+            humantime::format_duration(Duration::from_secs(now.as_secs())),
+            humantime::format_duration(total)
+        ),
+        TestLength::Requests(total) => format!("{}/{} requests", collector.requests_done, total),
+    };
+    if now < stats.warmup {
+        at.push_str(" warm-up");
+    }
+
+    let report = &collector.report;
+    let (requests, errors) = (report.total_requests, report.failed + report.timed_out);
+    let window = collector.timeline.window(now);
+    format!(
+        "[{}] {} requests, {} errors | last {}: {:.1} rps, {:.2}% errors, p50 {}, p99 {}",
+        at,
+        requests,
+        errors,
+        humantime::format_duration(stats.window),
+        window.requests_per_sec,
+        window.error_rate * 100.0,
+        format_latency(window.latencies.percentile(50.0)),
+        format_latency(window.latencies.percentile(99.0)),
+    )
+}
+
+/// what the run came down to, printed once it is over whether or not the tui was up
+pub fn print_summary(
+    out: &mut impl Write,
+    results: &RunResults,
+    percentiles: &[f64],
+) -> io::Result<()> {
+    let report = &results.report;
+    writeln!(
+        out,
+        "{:<14}{} ({} succeeded, {} failed, {} timed out) in {}, {:.1} rps",
+        "requests",
+        report.total_requests,
+        report.succeeded,
+        report.failed,
+        report.timed_out,
+        humantime::format_duration(Duration::from_millis(results.elapsed.as_millis() as u64)),
+        results.rps()
+    )?;
+    writeln!(
+        out,
+        "{:<14}{:.2} MB sent, {:.2} MB received",
+        "transfer",
+        megabytes(report.bytes_sent),
+        megabytes(report.bytes_received)
+    )?;
+
+    let status_codes = results
+        .status_codes
+        .by_code()
+        .map(|(code, count)| format!("{}: {}", code, count))
+        .collect::<Vec<_>>();
+    if !status_codes.is_empty() {
+        writeln!(out, "{:<14}{}", "status codes", status_codes.join(", "))?;
+    }
+
+    writeln!(
+        out,
+        "{:<14}{}",
+        "latency",
+        results.latencies.summary(percentiles)
+    )?;
+    if let Some(uncorrected) = &results.uncorrected_latencies {
+        writeln!(
+            out,
+            "{:<14}{}",
+            "uncorrected",
+            uncorrected.summary(percentiles)
+        )?;
+    }
+    Ok(())
+}
+
+/// `result` with a closed stdout, e.g. piped into `head`, taken as nothing left to print
+pub fn unless_broken_pipe(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
@@ -0,0 +1,243 @@
+use crate::types::format_latency;
+use hdrhistogram::serialization::{Deserializer, Serializer, V2DeflateSerializer};
+use hdrhistogram::{CreationError, Histogram};
+use serde::de::{self, Deserialize};
+use serde::ser::{self, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// a latency distribution in fixed memory. every latency is recorded in O(1), in
+/// microseconds, and read back accurate to the configured number of significant digits
+#[derive(Clone)]
+pub struct LatencyHistogram {
+    inner: Histogram<u64>,
+}
+
+impl LatencyHistogram {
+    /// `significant_digits` between 0 and 5, more digits take more memory. the
+    /// histogram grows to fit the largest latency recorded
+    pub fn new(significant_digits: u8) -> Result<Self, CreationError> {
+        Ok(LatencyHistogram {
+            inner: Histogram::new(significant_digits)?,
+        })
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let micros = latency.as_micros() as u64;
+        // record grows the histogram to fit, saturating_record would clamp instead
+        if self.inner.record(micros).is_err() {
+            self.inner.saturating_record(micros);
+        }
+    }
+
+    /// adds everything recorded in `other`, e.g. from another worker or agent
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        // both histograms resize themselves, which makes adding them infallible
+        self.inner
+            .add(&other.inner)
+            .expect("auto resizing histograms can always be added");
+    }
+
+    /// latency `percentile` percent of the recorded latencies are at or below, 0 if
+    /// nothing was recorded yet
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        self.percentiles(&[percentile])[0]
+    }
+
+    /// same as `percentile` for several percentiles at once. interpolates linearly
+    /// between the two closest ranks, like numpy's and excel's PERCENTILE.INC do
+    pub fn percentiles(&self, percentiles: &[f64]) -> Vec<Duration> {
+        let count = self.inner.len();
+        if count == 0 {
+            return vec![Duration::ZERO; percentiles.len()];
+        }
+
+        // (value, number of latencies at or below it) for every bucket with something in it
+        let mut seen = 0;
+        let cumulative = self
+            .inner
+            .iter_recorded()
+            .map(|bucket| {
+                seen += bucket.count_at_value();
+                (
+                    self.inner.median_equivalent(bucket.value_iterated_to()) as f64,
+                    seen,
+                )
+            })
+            .collect::<Vec<_>>();
+        // value of the latency at `rank` (from 0) in ascending order
+        let at_rank = |rank: u64| {
+            let indx = cumulative.partition_point(|&(_, seen)| seen <= rank);
+            cumulative[indx.min(cumulative.len() - 1)].0
+        };
+
+        percentiles
+            .iter()
+            .map(|percentile| {
+                let rank = percentile.clamp(0.0, 100.0) / 100.0 * (count - 1) as f64;
+                let (lower, upper) = (at_rank(rank.floor() as u64), at_rank(rank.ceil() as u64));
+                let micros = lower + (upper - lower) * rank.fract();
+                Duration::from_secs_f64(micros / 1_000_000.0)
+            })
+            .collect()
+    }
+
+    /// the latencies cut into `buckets` from the fastest to the slowest, each one
+    /// `(from, to, count)`. bucket bounds grow exponentially so a long tail doesn't
+    /// squash everything else into the first bucket. empty if nothing was recorded
+    pub fn distribution(&self, buckets: usize) -> Vec<(Duration, Duration, u64)> {
+        if self.inner.is_empty() || buckets == 0 {
+            return Vec::new();
+        }
+        let (min, max) = (
+            self.inner.min().max(1) as f64,
+            self.inner.max().max(1) as f64,
+        );
+        // ln of how many times slower the slowest latency is than the fastest
+        let growth = (max / min).ln().max(f64::EPSILON);
+        let bound = |indx: usize| {
+            Duration::from_secs_f64(
+                min * (growth * indx as f64 / buckets as f64).exp() / 1_000_000.0,
+            )
+        };
+
+        let mut counts = vec![0; buckets];
+        for bucket in self.inner.iter_recorded() {
+            let value = self.inner.median_equivalent(bucket.value_iterated_to()) as f64;
+            let indx = ((value.clamp(min, max) / min).ln() / growth * buckets as f64) as usize;
+            counts[indx.min(buckets - 1)] += bucket.count_at_value();
+        }
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(indx, count)| (bound(indx), bound(indx + 1), count))
+            .collect()
+    }
+
+    pub fn summary(&self, percentiles: &[f64]) -> LatencySummary {
+        let micros = |value: f64| Duration::from_secs_f64(value / 1_000_000.0);
+        LatencySummary {
+            count: self.inner.len(),
+            min: Duration::from_micros(self.inner.min()),
+            max: Duration::from_micros(self.inner.max()),
+            mean: micros(self.inner.mean()),
+            stddev: micros(self.inner.stdev()),
+            percentiles: percentiles
+                .iter()
+                .copied()
+                .zip(self.percentiles(percentiles))
+                .collect(),
+        }
+    }
+}
+
+/// the numbers that describe a latency distribution
+pub struct LatencySummary {
+    pub count: u64,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub stddev: Duration,
+    /// (percentile, latency) in the order they were asked for
+    pub percentiles: Vec<(f64, Duration)>,
+}
+
+/// `min 0.958ms, p50 2.929ms, …, max 5.027ms, mean 2.844ms, stddev 0.784ms`
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "min {}", format_latency(self.min))?;
+        for (percentile, value) in &self.percentiles {
+            write!(f, ", p{} {}", percentile, format_latency(*value))?;
+        }
+        write!(
+            f,
+            ", max {}, mean {}, stddev {}",
+            format_latency(self.max),
+            format_latency(self.mean),
+            format_latency(self.stddev)
+        )
+    }
+}
+
+// serialized in hdrhistogram's own compressed format, which every hdr implementation reads
+impl Serialize for LatencyHistogram {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut buf = Vec::new();
+        V2DeflateSerializer::new()
+            .serialize(&self.inner, &mut buf)
+            .map_err(|e| ser::Error::custom(format!("{:?}", e)))?;
+        serializer.serialize_bytes(&buf)
+    }
+}
+
+impl<'de> Deserialize<'de> for LatencyHistogram {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let buf = Vec::<u8>::deserialize(deserializer)?;
+        let mut inner: Histogram<u64> = Deserializer::new()
+            .deserialize(&mut buf.as_slice())
+            .map_err(|e| de::Error::custom(format!("{:?}", e)))?;
+        inner.auto(true);
+        Ok(LatencyHistogram { inner })
+    }
+}
+
+pub fn parse_significant_digits(raw: &str) -> Result<u8, String> {
+    raw.parse::<u8>()
+        .ok()
+        .filter(|digits| *digits <= 5)
+        .ok_or_else(|| format!("significant digits has to be 0 to 5, got {:?}", raw))
+}
+
+/// one percentile of a `--percentiles` list, from 0 to 100
+pub fn parse_percentile(raw: &str) -> Result<f64, String> {
+    raw.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|p| (0.0..=100.0).contains(p))
+        .ok_or_else(|| format!("percentiles have to be between 0 and 100, got {:?}", raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(micros: &[u64]) -> LatencyHistogram {
+        let mut histogram = LatencyHistogram::new(3).unwrap();
+        for &latency in micros {
+            histogram.record(Duration::from_micros(latency));
+        }
+        histogram
+    }
+
+    fn micros(latencies: Vec<Duration>) -> Vec<u64> {
+        latencies
+            .into_iter()
+            .map(|latency| (latency.as_secs_f64() * 1_000_000.0).round() as u64)
+            .collect()
+    }
+
+    #[test]
+    fn percentiles_interpolate_between_ranks() {
+        let histogram = histogram(&[100, 200, 300, 400]);
+        assert_eq!(
+            micros(histogram.percentiles(&[0.0, 50.0, 75.0, 100.0])),
+            [100, 250, 325, 400]
+        );
+    }
+
+    #[test]
+    fn percentiles_land_on_repeated_latencies() {
+        let histogram = histogram(&[100, 100, 100, 300]);
+        assert_eq!(micros(histogram.percentiles(&[50.0, 90.0])), [100, 240]);
+    }
+
+    #[test]
+    fn percentiles_of_an_empty_histogram_are_zero() {
+        let histogram = histogram(&[]);
+        assert_eq!(
+            histogram.percentiles(&[50.0, 99.0]),
+            [Duration::ZERO, Duration::ZERO]
+        );
+        assert_eq!(histogram.percentile(99.9), Duration::ZERO);
+    }
+}
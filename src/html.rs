@@ -0,0 +1,554 @@
+use crate::arrival::Arrival;
+use crate::output::ReportConfig;
+use crate::types::{format_latency, megabytes, millis};
+use crate::{RunResults, TestConfig, TestLength};
+use std::time::Duration;
+
+// every chart is drawn in this coordinate space and scales with the page
+const WIDTH: f64 = 860.0;
+const HEIGHT: f64 = 240.0;
+// room around the plot for the axis labels
+const LEFT: f64 = 64.0;
+const RIGHT: f64 = 16.0;
+const TOP: f64 = 12.0;
+const BOTTOM: f64 = 32.0;
+
+const COLORS: [&str; 6] = [
+    "#2b6cb0", "#dd6b20", "#38a169", "#805ad5", "#d53f8c", "#718096",
+];
+const ERROR_COLOR: &str = "#e53e3e";
+const HISTOGRAM_BUCKETS: usize = 40;
+
+const STYLE: &str = "
+body { font: 14px/1.4 -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; color: #1a202c; max-width: 920px; margin: 24px auto; padding: 0 16px; }
+h1 { font-size: 22px; margin-bottom: 4px; }
+h2 { font-size: 16px; margin: 28px 0 8px; }
+.sub { color: #718096; margin-top: 0; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: 4px 8px; border-bottom: 1px solid #e2e8f0; vertical-align: top; }
+th { width: 160px; font-weight: 600; color: #4a5568; }
+.passed { color: #2f855a; }
+.failed { color: #c53030; font-weight: 600; }
+.legend span { margin-right: 16px; }
+svg { width: 100%; height: auto; font-size: 11px; }
+svg text { fill: #4a5568; }
+pre { background: #f7fafc; padding: 8px; overflow-x: auto; font-size: 12px; }
+";
+
+/// one page with the parameters, summary and charts of a run. inline svg and css
+/// only, so it opens anywhere without a network
+pub fn render(results: &RunResults, config: &TestConfig) -> String {
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>xctl report</title>\n<style>{}</style>\n</head>\n<body>\n",
+        STYLE
+    );
+    let targets = config.load.targets.list();
+    page.push_str(&format!(
+        "<h1>xctl report</h1>\n<p class=\"sub\">{}{}{}</p>\n",
+        escape(&targets[0].url),
+        if targets.len() > 1 {
+            format!(" and {} more targets", targets.len() - 1)
+        } else {
+            String::new()
+        },
+        if results.completed {
+            ""
+        } else {
+            ", stopped early"
+        }
+    ));
+
+    page.push_str("<h2>Summary</h2>\n");
+    page.push_str(&table(&summary(results, config)));
+    page.push_str("<h2>Latency over time</h2>\n");
+    page.push_str(&latency_over_time(results, &config.stats.percentiles));
+    page.push_str("<h2>Latency distribution</h2>\n");
+    page.push_str(&latency_histogram(results));
+    page.push_str("<h2>Throughput and errors over time</h2>\n");
+    page.push_str(&throughput_over_time(results));
+    page.push_str("<h2>Status codes</h2>\n");
+    page.push_str(&status_codes(results));
+    page.push_str("<h2>Parameters</h2>\n");
+    page.push_str(&table(&parameters(config)));
+
+    // everything else about the config, as the json report has it with header values
+    // and the body left out, this page is meant to be passed around
+    page.push_str(&format!(
+        "<details><summary>full config</summary><pre>{}</pre></details>\n",
+        escape(&serde_json::to_string_pretty(&ReportConfig::new(config)).unwrap_or_default())
+    ));
+
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+/// (name, html) rows, the html is expected to be escaped already
+fn table(rows: &[(&str, String)]) -> String {
+    let mut html = String::from("<table>\n");
+    for (name, value) in rows {
+        html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", name, value));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+fn summary(results: &RunResults, config: &TestConfig) -> Vec<(&'static str, String)> {
+    let report = &results.report;
+    let mut rows = vec![
+        (
+            "requests",
+            format!(
+                "{} ({} succeeded, {} failed, {} timed out)",
+                report.total_requests, report.succeeded, report.failed, report.timed_out
+            ),
+        ),
+        (
+            "elapsed",
+            humantime::format_duration(Duration::from_millis(results.elapsed.as_millis() as u64))
+                .to_string(),
+        ),
+        ("throughput", format!("{:.1} rps", results.rps())),
+        (
+            "error rate",
+            results
+                .error_rate()
+                .map(|rate| format!("{:.2}%", rate * 100.0))
+                .unwrap_or_else(|| "n/a".to_string()),
+        ),
+        (
+            "transfer",
+            format!(
+                "{:.2} MB sent, {:.2} MB received",
+                megabytes(report.bytes_sent),
+                megabytes(report.bytes_received)
+            ),
+        ),
+    ];
+
+    rows.push((
+        "latency",
+        results
+            .latencies
+            .summary(&config.stats.percentiles)
+            .to_string(),
+    ));
+
+    let transport_errors = report
+        .transport_errors
+        .counts()
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(label, count)| format!("{}: {}", label, count))
+        .collect::<Vec<_>>();
+    if !transport_errors.is_empty() {
+        rows.push(("transport errors", transport_errors.join(", ")));
+    }
+
+    if let Some(apdex) = results.apdex {
+        rows.push(("apdex", apdex.to_string()));
+    }
+    if let Some(budget) = results.error_budget {
+        rows.push(("error budget", budget.to_string()));
+    }
+
+    for threshold in &config.thresholds {
+        let verdict = threshold.check(results);
+        let class = if verdict.passed { "passed" } else { "failed" };
+        rows.push((
+            "threshold",
+            format!(
+                "<span class=\"{}\">{}</span>",
+                class,
+                escape(&verdict.to_string())
+            ),
+        ));
+    }
+    rows
+}
+
+fn parameters(config: &TestConfig) -> Vec<(&'static str, String)> {
+    let load = &config.load;
+    let targets = load.targets.list();
+    let mut rows = vec![
+        (
+            "targets",
+            targets
+                .iter()
+                .map(|target| {
+                    if targets.len() > 1 {
+                        format!("{} (weight {})", escape(&target.url), target.weight)
+                    } else {
+                        escape(&target.url)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("<br>"),
+        ),
+        ("method", load.spec.method.to_string()),
+        (
+            "load",
+            if load.profile.is_staged() {
+                load.profile
+                    .stages()
+                    .iter()
+                    .map(|stage| escape(&stage.to_string()))
+                    .collect::<Vec<_>>()
+                    .join("<br>")
+            } else {
+                format!("{} rps", load.profile.stages()[0].to)
+            },
+        ),
+        (
+            "arrival",
+            match load.arrival {
+                Arrival::Constant => "constant".to_string(),
+                Arrival::Poisson => "poisson".to_string(),
+                Arrival::Burst { on, off } => format!(
+                    "bursts of {} every {}",
+                    humantime::format_duration(on),
+                    humantime::format_duration(on + off)
+                ),
+            },
+        ),
+        (
+            "length",
+            match load.test_length {
+                TestLength::Duration(duration) => humantime::format_duration(duration).to_string(),
+                TestLength::Requests(requests) => format!("{} requests", requests),
+            },
+        ),
+        ("workers", load.concurrent_clients.to_string()),
+        (
+            "timeout",
+            load.spec
+                .timeout
+                .map(|timeout| humantime::format_duration(timeout).to_string())
+                .unwrap_or_else(|| "none".to_string()),
+        ),
+    ];
+    if !config.stats.warmup.is_zero() {
+        rows.push((
+            "warm-up",
+            humantime::format_duration(config.stats.warmup).to_string(),
+        ));
+    }
+    if let Some(seed) = load.seed {
+        rows.push(("seed", seed.to_string()));
+    }
+    rows
+}
+
+fn latency_over_time(results: &RunResults, percentiles: &[f64]) -> String {
+    let seconds = results.timeline.finished_seconds();
+    let series = percentiles
+        .iter()
+        .enumerate()
+        .map(|(indx, percentile)| Series {
+            name: format!("p{}", percentile),
+            color: COLORS[indx % COLORS.len()],
+            points: seconds
+                .iter()
+                .enumerate()
+                // a second without requests has no latencies to show
+                .filter(|(_, second)| second.requests > 0)
+                .map(|(at, second)| (at as f64 + 0.5, millis(second.percentiles[indx].1)))
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    line_chart(&series, seconds.len(), results.warmup, false, format_ms)
+}
+
+fn throughput_over_time(results: &RunResults) -> String {
+    let seconds = results.timeline.finished_seconds();
+    let series = [
+        Series {
+            name: "requests/s".to_string(),
+            color: COLORS[0],
+            points: seconds
+                .iter()
+                .enumerate()
+                .map(|(at, second)| (at as f64 + 0.5, second.requests as f64))
+                .collect(),
+        },
+        Series {
+            name: "errors/s".to_string(),
+            color: ERROR_COLOR,
+            points: seconds
+                .iter()
+                .enumerate()
+                .map(|(at, second)| (at as f64 + 0.5, second.errors as f64))
+                .collect(),
+        },
+    ];
+    line_chart(&series, seconds.len(), results.warmup, true, format_count)
+}
+
+fn latency_histogram(results: &RunResults) -> String {
+    let buckets = results.latencies.distribution(HISTOGRAM_BUCKETS);
+    if buckets.is_empty() {
+        return no_data();
+    }
+    let most = buckets
+        .iter()
+        .map(|(_, _, count)| *count)
+        .max()
+        .unwrap_or(0);
+    let (ticks, top) = ticks(most as f64, true);
+    let bar_width = (WIDTH - LEFT - RIGHT) / buckets.len() as f64;
+    let y = |count: f64| HEIGHT - BOTTOM - count / top * (HEIGHT - TOP - BOTTOM);
+
+    let mut svg = open_svg(HEIGHT);
+    y_axis(&mut svg, &ticks, y, format_count);
+    for (indx, (from, to, count)) in buckets.iter().enumerate() {
+        let x = LEFT + indx as f64 * bar_width;
+        svg.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\">\
+             <title>{} to {}: {} requests</title></rect>\n",
+            x + 1.0,
+            y(*count as f64),
+            (bar_width - 2.0).max(1.0),
+            HEIGHT - BOTTOM - y(*count as f64),
+            COLORS[0],
+            format_latency(*from),
+            format_latency(*to),
+            count
+        ));
+        // every few bucket bounds are enough to read the (log) scale off
+        if indx % 8 == 0 {
+            x_label(&mut svg, x, &format_ms(millis(*from)));
+        }
+    }
+    x_label(
+        &mut svg,
+        WIDTH - RIGHT,
+        &format_ms(millis(buckets[buckets.len() - 1].1)),
+    );
+    svg.push_str("</svg>\n");
+    format!(
+        "<p class=\"sub\">coordinated omission corrected, buckets grow exponentially</p>\n{}",
+        svg
+    )
+}
+
+fn status_codes(results: &RunResults) -> String {
+    let mut rows = results
+        .status_codes
+        .by_code()
+        .map(|(code, count)| {
+            let color = match code {
+                200..=299 => COLORS[2],
+                300..=399 => COLORS[0],
+                400..=499 => COLORS[1],
+                _ => ERROR_COLOR,
+            };
+            (code.to_string(), count, color)
+        })
+        .collect::<Vec<_>>();
+    let no_response = (results.report.total_requests as u64)
+        .saturating_sub(rows.iter().map(|(_, count, _)| count).sum::<u64>());
+    if no_response > 0 {
+        rows.push(("no response".to_string(), no_response, COLORS[5]));
+    }
+    if rows.is_empty() {
+        return no_data();
+    }
+
+    let total = results.report.total_requests.max(1) as f64;
+    let most = rows.iter().map(|(_, count, _)| *count).max().unwrap_or(1) as f64;
+    let (label_width, row_height) = (90.0, 24.0);
+    let bar_room = WIDTH - label_width - 140.0;
+
+    let mut svg = open_svg(rows.len() as f64 * row_height + 8.0);
+    for (indx, (label, count, color)) in rows.iter().enumerate() {
+        let y = 4.0 + indx as f64 * row_height;
+        let width = (*count as f64 / most * bar_room).max(1.0);
+        svg.push_str(&format!(
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\n\
+             <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"/>\n\
+             <text x=\"{:.1}\" y=\"{:.1}\">{} ({:.1}%)</text>\n",
+            label_width - 8.0,
+            y + 15.0,
+            label,
+            label_width,
+            y + 3.0,
+            width,
+            row_height - 6.0,
+            color,
+            label_width + width + 6.0,
+            y + 15.0,
+            count,
+            *count as f64 / total * 100.0
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// a named line through (x, y) points
+struct Series {
+    name: String,
+    color: &'static str,
+    points: Vec<(f64, f64)>,
+}
+
+/// one line per series over the seconds of the run, with the warm-up shaded. `whole`
+/// when the values are counts
+fn line_chart(
+    series: &[Series],
+    seconds: usize,
+    warmup: Duration,
+    whole: bool,
+    format_y: fn(f64) -> String,
+) -> String {
+    if seconds == 0 {
+        return no_data();
+    }
+    let most = series
+        .iter()
+        .flat_map(|series| series.points.iter().map(|(_, y)| *y))
+        .fold(0.0, f64::max);
+    let (y_ticks, top) = ticks(most, whole);
+    let end = seconds as f64;
+    let x = |at: f64| LEFT + at / end * (WIDTH - LEFT - RIGHT);
+    let y = |value: f64| HEIGHT - BOTTOM - value / top * (HEIGHT - TOP - BOTTOM);
+
+    let mut svg = open_svg(HEIGHT);
+    if !warmup.is_zero() {
+        let until = x(warmup.as_secs_f64().min(end));
+        svg.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"#edf2f7\"/>\n\
+             <text x=\"{:.1}\" y=\"{:.1}\">warm-up</text>\n",
+            LEFT,
+            TOP,
+            until - LEFT,
+            HEIGHT - TOP - BOTTOM,
+            LEFT + 4.0,
+            TOP + 12.0
+        ));
+    }
+    y_axis(&mut svg, &y_ticks, y, format_y);
+
+    // whole seconds only, however short the run
+    let step = nice_step(end).max(1.0);
+    let mut at = 0.0;
+    while at <= end {
+        x_label(&mut svg, x(at), &format!("{}s", at));
+        at += step;
+    }
+
+    for series in series {
+        let points = series
+            .points
+            .iter()
+            .map(|(at, value)| format!("{:.1},{:.1}", x(*at), y(*value)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        svg.push_str(&format!(
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"/>\n",
+            points, series.color
+        ));
+    }
+    svg.push_str("</svg>\n");
+
+    let legend = series
+        .iter()
+        .map(|series| {
+            format!(
+                "<span><b style=\"color:{}\">&#9632;</b> {}</span>",
+                series.color,
+                escape(&series.name)
+            )
+        })
+        .collect::<String>();
+    format!("<div class=\"legend\">{}</div>\n{}", legend, svg)
+}
+
+fn open_svg(height: f64) -> String {
+    format!(
+        "<svg viewBox=\"0 0 {} {}\" xmlns=\"http://www.w3.org/2000/svg\">\n",
+        WIDTH, height
+    )
+}
+
+/// gridlines across the plot with their values on the left
+fn y_axis(svg: &mut String, ticks: &[f64], y: impl Fn(f64) -> f64, format_y: fn(f64) -> String) {
+    for tick in ticks {
+        svg.push_str(&format!(
+            "<line x1=\"{:.1}\" x2=\"{:.1}\" y1=\"{:.1}\" y2=\"{:.1}\" stroke=\"#e2e8f0\"/>\n\
+             <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\n",
+            LEFT,
+            WIDTH - RIGHT,
+            y(*tick),
+            y(*tick),
+            LEFT - 6.0,
+            y(*tick) + 4.0,
+            format_y(*tick)
+        ));
+    }
+}
+
+fn x_label(svg: &mut String, x: f64, label: &str) {
+    svg.push_str(&format!(
+        "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>\n",
+        x,
+        HEIGHT - BOTTOM + 16.0,
+        label
+    ));
+}
+
+fn no_data() -> String {
+    "<p class=\"sub\">nothing was measured</p>\n".to_string()
+}
+
+/// 1, 2 or 5 times a power of ten, so about five of them fit in `max`
+fn nice_step(max: f64) -> f64 {
+    let raw = (max / 5.0).max(f64::MIN_POSITIVE);
+    let magnitude = 10_f64.powf(raw.log10().floor());
+    let nice = match raw / magnitude {
+        x if x <= 1.0 => 1.0,
+        x if x <= 2.0 => 2.0,
+        x if x <= 5.0 => 5.0,
+        _ => 10.0,
+    };
+    nice * magnitude
+}
+
+/// evenly spaced axis values from 0 up to the first one at or above `max`, and that
+/// one. `whole` keeps them to whole numbers, for counts
+fn ticks(max: f64, whole: bool) -> (Vec<f64>, f64) {
+    // an axis for nothing at all still needs a scale
+    let max = if max > 0.0 { max } else { 1.0 };
+    let step = if whole {
+        nice_step(max).max(1.0)
+    } else {
+        nice_step(max)
+    };
+    let steps = (max / step).ceil() as usize;
+    (
+        (0..=steps).map(|indx| indx as f64 * step).collect(),
+        steps as f64 * step,
+    )
+}
+
+fn escape(raw: &str) -> String {
+    raw.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_count(count: f64) -> String {
+    format!("{:.0}", count)
+}
+
+/// short enough for an axis label
+fn format_ms(ms: f64) -> String {
+    if ms >= 100.0 {
+        format!("{:.0}ms", ms)
+    } else if ms >= 10.0 {
+        format!("{:.1}ms", ms)
+    } else {
+        format!("{:.2}ms", ms)
+    }
+}
@@ -1,26 +1,56 @@
+use rand::{rngs::StdRng, SeedableRng};
 use std::{
-    str::FromStr,
+    io::{self, IsTerminal, Write},
+    process::ExitCode,
     sync::Arc,
     time::{Duration, Instant},
 };
 use tokio::sync::mpsc::{self};
+mod arrival;
+mod client;
+mod collector;
+mod compare;
+mod distributed;
+mod headless;
+mod histogram;
+mod html;
+mod output;
+mod profile;
+mod request;
+mod request_log;
+mod slo;
+mod targets;
+mod threshold;
 mod tui_backend;
 mod types;
-use std::num::ParseIntError;
-use types::{MachineDetails, Report};
-use tui::backend::CrosstermBackend;
-use tui::Terminal;
-use std::io::{self, Stdout};
+mod window;
+use arrival::{Arrival, Schedule};
+use client::{ClientOptions, ConnectionInfo, HttpClient};
+use collector::Record;
+use compare::SavedRun;
+use distributed::AgentOptions;
+use hyper::body::HttpBody;
+use output::Output;
+use profile::Profile;
+use request::RequestSpec;
+use request_log::{RequestLog, RequestRecord};
+use serde::{Deserialize, Serialize};
+use targets::Targets;
+use threshold::{Threshold, Verdict};
+use types::{
+    MachineDetails, Phases, Report, RunResults, StatusCodes, TestLength, Throughput,
+    TransportError, TransportErrors,
+};
 
-pub struct Tower {
+pub struct Tower<R> {
     // send end
-    sender: tokio::sync::mpsc::Sender<Arc<Report>>,
+    sender: tokio::sync::mpsc::Sender<R>,
     // receiver end
-    receiver: tokio::sync::mpsc::Receiver<Arc<Report>>,
+    receiver: tokio::sync::mpsc::Receiver<R>,
 }
 
-impl Tower {
-    fn new() -> Tower {
+impl<R> Tower<R> {
+    fn new() -> Tower<R> {
         let (tx, rx) = mpsc::channel(100);
         Tower {
             sender: tx,
@@ -29,109 +59,642 @@ impl Tower {
     }
 }
 
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
 /// loadtest the given url with the parameters
 #[derive(StructOpt)]
+#[structopt(setting = AppSettings::SubcommandsNegateReqs)]
 struct Cli {
-    /// duration of test
-    #[structopt(short = "d", long = "duration", default_value = "25")]
-    duration: String,
+    #[structopt(flatten)]
+    test: TestArgs,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+#[allow(clippy::large_enum_variant)]
+enum Command {
+    /// wait for a controller to hand out a load test, run it and stream the results back
+    Agent {
+        /// address to accept controllers on, only this machine by default. open it up
+        /// with e.g. 0.0.0.0:7070
+        #[structopt(long = "listen", default_value = "127.0.0.1:7070")]
+        listen: String,
+        /// shared secret controllers have to present before they can run a test
+        #[structopt(long = "token", env = "XCTL_TOKEN", hide_env_values = true)]
+        token: String,
+        /// most concurrent clients a controller may ask this agent for
+        #[structopt(long = "max-concurrency", default_value = "1000")]
+        max_concurrency: u64,
+    },
+    /// run the load test from a set of agents together, the rate and request count are
+    /// split evenly between them while concurrency applies to each agent
+    Controller {
+        /// `host:port` of an agent, can be repeated
+        #[structopt(short = "a", long = "agent", required = true, number_of_values = 1)]
+        agents: Vec<String>,
+        /// the secret the agents were started with
+        #[structopt(long = "token", env = "XCTL_TOKEN", hide_env_values = true)]
+        token: String,
+        #[structopt(flatten)]
+        test: TestArgs,
+    },
+    /// compare two runs saved with --save, per percentile, in throughput and error rate
+    Compare {
+        /// the run to compare against
+        baseline: String,
+        /// the run that may have changed
+        candidate: String,
+        /// comma separated latency percentiles to compare
+        #[structopt(
+            long = "percentiles",
+            default_value = "50,90,95,99,99.9",
+            use_delimiter = true,
+            parse(try_from_str = histogram::parse_percentile)
+        )]
+        percentiles: Vec<f64>,
+    },
+}
+
+// what to load test and how, shared by local runs and the controller
+#[derive(StructOpt)]
+struct TestArgs {
+    /// duration of test, in seconds or e.g. 2m30s [default: 25]
+    #[structopt(short = "d", long = "duration", parse(try_from_str = parse_test_duration))]
+    duration: Option<Duration>,
+    /// stop after sending exactly this many requests instead of after a duration
+    #[structopt(short = "n", long = "requests", conflicts_with = "duration")]
+    requests: Option<u64>,
     /// number of concurrent clients
     #[structopt(short = "c", long = "concurrency", default_value = "10")]
     concurrent_clients: u64,
     /// queries per second
     #[structopt(short = "qps", long = "queries-per-second", default_value = "10")]
     qps: u64,
-    /// files to read target urls from
-    #[structopt(short = "f", long = "file")]
-    file:String,
+    /// file to read target urls from, one `<url> [weight]` per line
+    #[structopt(
+        short = "f",
+        long = "file",
+        required_unless = "url",
+        conflicts_with = "url"
+    )]
+    file: Option<String>,
+    /// single url to load test
     #[structopt(short = "u", long = "url")]
-    url:String,
+    url: Option<String>,
+    /// http method to send
+    #[structopt(short = "m", long = "method", default_value = "GET", parse(try_from_str = request::parse_method))]
+    method: hyper::Method,
+    /// extra request header as `Name: value`, can be repeated
+    #[structopt(short = "H", long = "header", number_of_values = 1)]
+    headers: Vec<String>,
+    /// request body
+    #[structopt(long = "body", conflicts_with = "body-file")]
+    body: Option<String>,
+    /// file to read the request body from
+    #[structopt(long = "body-file")]
+    body_file: Option<String>,
+    /// give up on a request after this long and count it as timed out, e.g. 5s
+    #[structopt(long = "timeout", parse(try_from_str = humantime::parse_duration))]
+    timeout: Option<Duration>,
+    /// also show latency measured from when a request actually started, next to the
+    /// coordinated omission corrected latency measured from when it was scheduled
+    #[structopt(long = "show-uncorrected")]
+    show_uncorrected: bool,
+    /// how requests arrive: constant, poisson or burst[:<on>/<off>] e.g. burst:500ms/2s
+    #[structopt(long = "arrival", default_value = "constant")]
+    arrival: Arrival,
+    /// seed for the random parts of a run (arrivals, target picks) to make it reproducible
+    #[structopt(long = "seed")]
+    seed: Option<u64>,
+    /// comma separated load stages to run instead of a fixed qps and duration, each one
+    /// `ramp:<rps>:<duration>` or `hold:<rps>:<duration>` e.g. ramp:500:60s,hold:500:5m
+    #[structopt(long = "stages", conflicts_with_all = &["duration", "requests", "stages-file"])]
+    stages: Option<String>,
+    /// file to read load stages from, one stage per line
+    #[structopt(long = "stages-file", conflicts_with_all = &["duration", "requests"])]
+    stages_file: Option<String>,
+    /// reuse connections between requests, false opens a new connection for every request
+    #[structopt(long = "keepalive", default_value = "true", parse(try_from_str))]
+    keepalive: bool,
+    /// max idle connections kept open per host
+    #[structopt(long = "max-idle-per-host")]
+    max_idle_per_host: Option<usize>,
+    /// how long idle connections are kept open, e.g. 30s
+    #[structopt(long = "idle-timeout", parse(try_from_str = humantime::parse_duration))]
+    idle_timeout: Option<Duration>,
+    /// set TCP_NODELAY on connections
+    #[structopt(long = "tcp-nodelay", default_value = "true", parse(try_from_str))]
+    tcp_nodelay: bool,
+    /// give up connecting to the target after this long, e.g. 2s
+    #[structopt(long = "connect-timeout", parse(try_from_str = humantime::parse_duration))]
+    connect_timeout: Option<Duration>,
+    /// give every worker its own http client and connection pool instead of sharing one
+    #[structopt(long = "client-per-worker")]
+    client_per_worker: bool,
+    /// redirects to follow before taking the 3xx as the response, 0 to follow none
+    #[structopt(long = "max-redirects", default_value = "10")]
+    max_redirects: usize,
+    /// significant digits latencies are kept accurate to, 0 to 5
+    #[structopt(long = "significant-digits", default_value = "3", parse(try_from_str = histogram::parse_significant_digits))]
+    significant_digits: u8,
+    /// comma separated latency percentiles to show
+    #[structopt(
+        long = "percentiles",
+        default_value = "50,90,95,99,99.9",
+        use_delimiter = true,
+        parse(try_from_str = histogram::parse_percentile)
+    )]
+    percentiles: Vec<f64>,
+    /// how far back the live (as opposed to cumulative) stats look, e.g. 10s
+    #[structopt(long = "window", default_value = "10s", parse(try_from_str = humantime::parse_duration))]
+    window: Duration,
+    /// print a plain progress line every --progress-interval instead of drawing the
+    /// tui, the default when stdout is not a terminal
+    #[structopt(long = "no-tui")]
+    no_tui: bool,
+    /// how often to print a progress line without the tui
+    #[structopt(long = "progress-interval", default_value = "5s", parse(try_from_str = humantime::parse_duration))]
+    progress_interval: Duration,
+    /// how long at the start of the test load is sent without counting towards the
+    /// stats, to let caches, connection pools and the target's jit warm up, e.g. 30s
+    #[structopt(
+        long = "warmup",
+        conflicts_with = "requests",
+        parse(try_from_str = humantime::parse_duration)
+    )]
+    warmup: Option<Duration>,
+    /// latency target T for the apdex score, requests up to T satisfy, up to 4T are
+    /// tolerated and anything slower or failed frustrates, e.g. 300ms
+    #[structopt(long = "apdex-t", parse(try_from_str = humantime::parse_duration))]
+    apdex_t: Option<Duration>,
+    /// availability target in percent to show how much of its error budget the run
+    /// used, e.g. 99.9
+    #[structopt(long = "availability", parse(try_from_str = slo::parse_availability))]
+    availability: Option<f64>,
+    /// limit the run has to stay within or xctl exits with an error, checked once the
+    /// run is over, can be repeated. `<metric><op><limit>` with metric p<percentile>,
+    /// mean, max, error_rate or rps and op <, <=, > or >=, e.g. p99<250ms, error_rate<0.5%
+    #[structopt(long = "threshold", number_of_values = 1)]
+    thresholds: Vec<Threshold>,
+    /// save the run's latency distribution and counters to this file once it is over,
+    /// for `xctl compare` or --baseline
+    #[structopt(long = "save")]
+    save: Option<String>,
+    /// run saved with --save to compare this run against once it is over
+    #[structopt(long = "baseline")]
+    baseline: Option<String>,
+    /// report to write once the run is over as `<format>=<path>`, can be repeated.
+    /// json=<path> writes the config, totals, percentiles, status codes, a per second
+    /// series and the machine details, html=<path> a page with charts of the run that
+    /// opens without a network
+    #[structopt(long = "output", number_of_values = 1)]
+    outputs: Vec<Output>,
+    /// log every request to a file as it completes, as `csv=<path>` or `jsonl=<path>`:
+    /// when it was scheduled and started, its latency, status, outcome, bytes and url
+    #[structopt(long = "request-log")]
+    request_log: Option<RequestLog>,
+}
+
+/// everything a load test run needs, resolved from the cli
+struct TestConfig {
+    load: LoadConfig,
+    stats: StatsConfig,
+    thresholds: Vec<Threshold>,
+    save: Option<String>,
+    baseline: Option<SavedRun>,
+    outputs: Vec<Output>,
+    request_log: Option<RequestLog>,
+}
+
+/// what the tower works out from the reports and shows. agents get it too, to add up
+/// their snapshots the same way
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StatsConfig {
+    show_uncorrected: bool,
+    significant_digits: u8,
+    percentiles: Vec<f64>,
+    window: Duration,
+    /// the first part of the test, left out of the stats
+    warmup: Duration,
+    /// plain progress lines instead of the tui
+    headless: bool,
+    progress_interval: Duration,
+    apdex_t: Option<Duration>,
+    /// percentage of requests that have to succeed, e.g. 99.9
+    availability: Option<f64>,
+}
+
+/// what to send, how fast and for how long. this is what a controller hands to its agents
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LoadConfig {
+    test_length: TestLength,
+    concurrent_clients: u64,
+    profile: Profile,
+    targets: Targets,
+    spec: RequestSpec,
+    arrival: Arrival,
+    seed: Option<u64>,
+    client: ClientOptions,
 }
 
 // #[tokio::main]
 // #[tokio::main(flavor = "current_thread")]
 #[tokio::main(flavor = "multi_thread", worker_threads = 10)]
-async fn main() -> Result<(), ()> {
-    let args = Cli::from_args();
-    let test_duration = args.duration.parse::<u64>().unwrap_or(25);
+async fn main() -> ExitCode {
+    // every error has been printed by the time it gets here, a failed run only
+    // needs the exit code
+    match run(Cli::from_args()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(()) => ExitCode::FAILURE,
+    }
+}
 
-    let readonly_url = Arc::new(args.url);
+async fn run(args: Cli) -> Result<(), ()> {
+    match args.command {
+        None => load_test(resolve(args.test)?).await,
+        Some(Command::Agent {
+            listen,
+            token,
+            max_concurrency,
+        }) => distributed::agent(
+            &listen,
+            AgentOptions {
+                token,
+                max_concurrency,
+            },
+        )
+        .await
+        .map_err(|e| eprintln!("{}", e)),
+        Some(Command::Controller {
+            agents,
+            token,
+            test,
+        }) => distributed::controller(&agents, &token, resolve(test)?).await,
+        Some(Command::Compare {
+            baseline,
+            candidate,
+            percentiles,
+        }) => {
+            let baseline = SavedRun::load(&baseline).map_err(|e| eprintln!("{}", e))?;
+            let candidate = SavedRun::load(&candidate).map_err(|e| eprintln!("{}", e))?;
+            let printed = compare::print_comparison(
+                &mut io::stdout().lock(),
+                &baseline,
+                &candidate,
+                &percentiles,
+            );
+            headless::unless_broken_pipe(printed).map_err(|e| eprintln!("{}", e))
+        }
+    }
+}
+
+/// a bare number is seconds like it always was, anything else a humantime duration
+fn parse_test_duration(raw: &str) -> Result<Duration, String> {
+    match raw.parse::<u64>() {
+        Ok(secs) => Ok(Duration::from_secs(secs)),
+        Err(_) => humantime::parse_duration(raw).map_err(|e| e.to_string()),
+    }
+}
+
+fn resolve(args: TestArgs) -> Result<TestConfig, ()> {
+    let profile = match (args.stages, args.stages_file) {
+        (Some(stages), _) => Profile::parse(&stages).map_err(|e| eprintln!("{}", e))?,
+        (None, Some(file)) => Profile::from_file(&file).map_err(|e| eprintln!("{}", e))?,
+        // a rate of 0 would never send a request, however long the test runs
+        (None, None) if args.qps == 0 => {
+            eprintln!("--queries-per-second has to be more than 0");
+            return Err(());
+        }
+        (None, None) => Profile::constant(args.qps as f64),
+    };
+
+    let test_length = match args.requests {
+        // stages decide how long the test runs
+        _ if profile.is_staged() => TestLength::Duration(profile.duration()),
+        Some(n) => TestLength::Requests(n),
+        None => TestLength::Duration(args.duration.unwrap_or(Duration::from_secs(25))),
+    };
+
+    let targets = match (args.file, args.url) {
+        (Some(file), _) => Targets::from_file(&file).map_err(|e| eprintln!("{}", e))?,
+        (None, Some(url)) => Targets::single(url).map_err(|e| eprintln!("{}", e))?,
+        (None, None) => unreachable!("structopt requires one of --file or --url"),
+    };
+
+    if let TestLength::Duration(d) = test_length {
+        if d.is_zero() {
+            eprintln!("the test has to last longer than 0");
+            return Err(());
+        }
+        if args.warmup.is_some_and(|warmup| warmup >= d) {
+            eprintln!("the warm-up has to be shorter than the test");
+            return Err(());
+        }
+    }
+    let warmup = args.warmup.unwrap_or_default();
+
+    // a bad baseline should stop the run before it starts, not after
+    let baseline = args
+        .baseline
+        .map(|path| SavedRun::load(&path))
+        .transpose()
+        .map_err(|e| eprintln!("{}", e))?;
+
+    let spec = RequestSpec::new(
+        args.method,
+        &args.headers,
+        args.body,
+        args.body_file,
+        args.timeout,
+    )
+    .map_err(|e| eprintln!("{}", e))?;
+
+    Ok(TestConfig {
+        load: LoadConfig {
+            test_length,
+            concurrent_clients: args.concurrent_clients,
+            profile,
+            targets,
+            spec,
+            arrival: args.arrival,
+            seed: args.seed,
+            client: ClientOptions {
+                keepalive: args.keepalive,
+                max_idle_per_host: args.max_idle_per_host,
+                idle_timeout: args.idle_timeout,
+                tcp_nodelay: args.tcp_nodelay,
+                connect_timeout: args.connect_timeout,
+                per_worker: args.client_per_worker,
+                max_redirects: args.max_redirects,
+            },
+        },
+        stats: StatsConfig {
+            show_uncorrected: args.show_uncorrected,
+            significant_digits: args.significant_digits,
+            percentiles: args.percentiles,
+            window: args.window,
+            warmup,
+            headless: args.no_tui || !io::stdout().is_terminal(),
+            progress_interval: args.progress_interval,
+            apdex_t: args.apdex_t,
+            availability: args.availability,
+        },
+        thresholds: args.thresholds,
+        save: args.save,
+        baseline,
+        outputs: args.outputs,
+        request_log: args.request_log,
+    })
+}
+
+async fn load_test(config: TestConfig) -> Result<(), ()> {
+    let start = Instant::now();
+    let (sender, tower) = spawn_tower(
+        start,
+        config.load.test_length,
+        Arc::new(config.load.profile.clone()),
+        config.stats.clone(),
+    );
+
+    let (request_log, log_writer) = match &config.request_log {
+        Some(log) => {
+            let (sender, writer) = log.spawn().map_err(|e| eprintln!("{}", e))?;
+            (Some(sender), Some(writer))
+        }
+        None => (None, None),
+    };
+
+    let load = tokio::spawn(generate_load(
+        config.load.clone(),
+        sender,
+        request_log,
+        start,
+    ));
+
+    let results = tower.await.map_err(|e| eprintln!("{}", e))??;
+    if results.completed {
+        // every report is in, so the load is already done
+        load.await.map_err(|e| eprintln!("{}", e))??;
+        // and with it every record, the writer only has to flush
+        if let Some(writer) = log_writer {
+            writer
+                .await
+                .map_err(|e| eprintln!("{}", e))?
+                .map_err(|e| eprintln!("{}", e))?;
+        }
+    }
+
+    conclude(&results, &config)
+}
+
+/// prints a summary of the run with its apdex and error budget, how it compares to the
+/// baseline and how it did against every threshold, and saves it and writes its reports
+/// if asked to. Err if it missed a threshold
+fn conclude(results: &RunResults, config: &TestConfig) -> Result<(), ()> {
+    let verdicts = config
+        .thresholds
+        .iter()
+        .map(|threshold| threshold.check(results))
+        .collect::<Vec<_>>();
+    let printed = print_results(&mut io::stdout().lock(), results, config, &verdicts);
+    headless::unless_broken_pipe(printed)
+        .map_err(|e| eprintln!("could not print the results: {}", e))?;
 
-    load_test(test_duration, args.concurrent_clients, args.qps, readonly_url).await?;
+    if let Some(path) = &config.save {
+        SavedRun::new(results)
+            .save(path)
+            .map_err(|e| eprintln!("{}", e))?;
+    }
+    for output in &config.outputs {
+        output
+            .write(results, config)
+            .map_err(|e| eprintln!("{}", e))?;
+    }
+
+    let thresholds = &config.thresholds;
+    if thresholds.is_empty() {
+        return Ok(());
+    }
+
+    let failed = verdicts.iter().filter(|verdict| !verdict.passed).count();
+    if !results.completed {
+        eprintln!("the run was stopped early, thresholds can't pass on a partial run");
+        Err(())
+    } else if failed > 0 {
+        eprintln!("{} of {} thresholds failed", failed, thresholds.len());
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+/// the summary, the comparison against a baseline and how every threshold did
+fn print_results(
+    out: &mut impl Write,
+    results: &RunResults,
+    config: &TestConfig,
+    verdicts: &[Verdict],
+) -> io::Result<()> {
+    headless::print_summary(out, results, &config.stats.percentiles)?;
+    if let Some(apdex) = results.apdex {
+        writeln!(out, "apdex {}", apdex)?;
+    }
+    if let Some(budget) = results.error_budget {
+        writeln!(out, "error budget {}", budget)?;
+    }
+
+    if let Some(baseline) = &config.baseline {
+        writeln!(out)?;
+        compare::print_comparison(
+            out,
+            baseline,
+            &SavedRun::new(results),
+            &config.stats.percentiles,
+        )?;
+        writeln!(out)?;
+    }
+    for verdict in verdicts {
+        writeln!(out, "{}", verdict)?;
+    }
     Ok(())
 }
 
-async fn load_test(test_duration: u64, concurrent_clients: u64, qps: u64, url:Arc<String>) -> Result<(), ()> {
-    let mut report_manager = Tower::new();
+/// starts the task that collects every report and draws the tui, reports go in
+/// through the returned sender. the task ends with the results once every sender has
+/// been dropped, or early if the user stops the run
+fn spawn_tower<R: Record + Send + 'static>(
+    start: Instant,
+    test_length: TestLength,
+    profile: Arc<Profile>,
+    stats: StatsConfig,
+) -> (
+    mpsc::Sender<R>,
+    tokio::task::JoinHandle<Result<RunResults, ()>>,
+) {
+    // only the receiver goes into the task, or the channel could never close
+    let Tower {
+        sender,
+        mut receiver,
+    } = Tower::new();
 
-    let csend = report_manager.sender.clone();
+    let tower = tokio::spawn(async move {
+        if stats.headless {
+            headless::write_to_stdout(&mut receiver, start, test_length, &stats)
+                .await
+                .map_err(|e| eprintln!("could not follow the run: {}", e))
+        } else {
+            tui_backend::write_to_t(&mut receiver, start, test_length, profile, &stats)
+                .await
+                .map_err(|e| eprintln!("could not draw the tui: {}", e))
+        }
+    });
+
+    (sender, tower)
+}
+
+/// sends the requests of a load test as scheduled from `start` and hands a report of
+/// every one of them to `csend`, and a record of it to `request_log` if there is one.
+/// returns once all of them are done
+pub async fn generate_load(
+    config: LoadConfig,
+    csend: mpsc::Sender<Arc<Report>>,
+    request_log: Option<flume::Sender<RequestRecord>>,
+    start: Instant,
+) -> Result<(), ()> {
+    let LoadConfig {
+        test_length,
+        concurrent_clients,
+        profile,
+        targets,
+        spec,
+        arrival,
+        seed,
+        client: client_options,
+    } = config;
+    let profile = Arc::new(profile);
+    let targets = Arc::new(targets);
+    let spec = Arc::new(spec);
 
-    let (tx, rx) = flume::unbounded();
+    // every job is the instant the load generator intended the request to go out at
+    let (tx, rx) = flume::unbounded::<Instant>();
     let workers: u64 = concurrent_clients;
     // load balancers are mapped to OS threads which are scheduled over cpus
     // that are scheduled and managed by tokio (os level scheduling also there). For now 10 threads.
 
+    // cloning a client shares its connection pool, so a shared client is built once
+    let clients = if client_options.per_worker {
+        (0..workers)
+            .map(|_| client_options.build())
+            .collect::<Result<Vec<_>, _>>()
+    } else {
+        client_options.build().map(|c| vec![c; workers as usize])
+    }
+    .map_err(|e| eprintln!("could not build http client: {}", e))?;
+
     let load_balancer = (0..workers)
-        .map(|_| {
+        .zip(clients)
+        .map(|(worker, client)| {
             let sendc = csend.clone();
             let rx = rx.clone();
-            // let read_url = &host;
-            let host_url = url.clone();
+            let targets = targets.clone();
+            let spec = spec.clone();
+            let request_log = request_log.clone();
 
             tokio::spawn(async move {
-                while let Ok(()) = rx.recv_async().await {
-                    match do_req(host_url.as_str()).await {
-                        Ok(request_result) => match sendc.send(request_result).await {
-                            Ok(_) => {}
-                            Err(_) => {
-                                println!("err while sending to channel");
-                                return;
-                            }
-                        },
-                        Err(_) => {}
+                // every worker picks its own targets so they don't contend on a shared rng
+                let mut rng = match seed {
+                    Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(worker + 1)),
+                    None => StdRng::from_entropy(),
+                };
+                while let Ok(scheduled_at) = rx.recv_async().await {
+                    let target = targets.pick(&mut rng);
+                    if let Ok(request_result) =
+                        do_req(&client, &spec, target.url.as_str(), scheduled_at).await
+                    {
+                        if let Some(log) = &request_log {
+                            // unbounded, the worker never waits on the disk
+                            let _ = log.send(RequestRecord::new(
+                                &request_result,
+                                scheduled_at - start,
+                                &target.url,
+                            ));
+                        }
+                        if sendc.send(request_result).await.is_err() {
+                            println!("err while sending to channel");
+                            return;
+                        }
                     }
                 }
             })
         })
         .collect::<Vec<_>>();
 
-    let start = Instant::now();
-    let dead_line = start + Duration::new(test_duration, 0);
-
-    let mut report = Report::new();
-
-    let tower = tokio::spawn(async move {
-
-        let _ = tui_backend::write_to_t(
-            &mut report,
-            &mut report_manager.receiver,
-            start,
-            Duration::new(test_duration, 0),
-        )
-        .await;
-    });
-
-    // qps is queries per second
-    let qps = qps;
-
     let load_gen = tokio::spawn(async move {
-        for i in 0.. {
-            // println!("{}'th attempt", i);
-            if std::time::Instant::now() > dead_line {
+        let mut schedule = Schedule::new(arrival, profile, seed);
+        for i in 0_u64.. {
+            // send times only depend on the arrival process and the load profile,
+            // never on how long the previous requests took
+            let offset = match schedule.next() {
+                Some(offset) => offset,
+                None => break,
+            };
+            let done = match test_length {
+                TestLength::Duration(d) => offset > d,
+                TestLength::Requests(n) => i >= n,
+            };
+            if done {
                 break;
             }
-            if tx.send_async(()).await.is_err() {
+
+            let scheduled_at = start + offset;
+            tokio::time::sleep_until(scheduled_at.into()).await;
+
+            if tx.send_async(scheduled_at).await.is_err() {
                 println!("GOT ERROR");
                 break;
             }
-            // waiting for this formula to make more sense in hindsight, i just found it somewhere,
-            // this is a shameless copy pasta.
-            let sleep_for =
-                (start + i as u32 * std::time::Duration::from_secs(1) / qps as u32).into();
-            // println!("sleeping for : {:?}", sleep_for);
-            tokio::time::sleep_until(sleep_for).await;
         }
     });
 
@@ -141,51 +704,130 @@ async fn load_test(test_duration: u64, concurrent_clients: u64, qps: u64, url:Ar
 
     let _ = load_gen.await;
 
-    let _ = tower.await;
-
     Ok(())
 }
 
-async fn do_req(host: &str) -> Result<Arc<Report>, ()> {
+/// `duration` is measured from when the request actually started, while
+/// `corrected_duration` is measured from `scheduled_at`, so time a job spent queued
+/// behind a stalled target counts against the latency instead of being omitted
+async fn do_req(
+    client: &HttpClient,
+    spec: &RequestSpec,
+    host: &str,
+    scheduled_at: Instant,
+) -> Result<Arc<Report>, ()> {
     let start_of_request = Instant::now();
 
+    // filled in as the request goes, so a timed out request still has what it got through
+    let mut phases = Phases::default();
+    // (sent, received), only known once the response has been read in full
+    let mut bytes = (0, 0);
+
+    // the status if there was a response, and what went wrong below http if anything
     let make_request = async {
-        match reqwest::get(host).await {
-            Ok(res) => {
-                if res.status() == 200 {
-                    Ok(Arc::new(Report {
-                        succeeded: 1,
-                        failed: 0,
-                        total_requests: 1,
-                        elapsed: 0,
-                        transaction_rate: 0.0,
-                        duration: start_of_request.elapsed(),
-                    }))
-                } else {
-                    Ok(Arc::new(Report {
-                        succeeded: 0,
-                        failed: 1,
-                        total_requests: 1,
-                        elapsed: 0,
-                        transaction_rate: 0.0,
-                        duration: start_of_request.elapsed(),
-                    }))
+        let mut req = match spec.build(host) {
+            Ok(req) => req,
+            Err(_) => return (None, Some(TransportError::Other)),
+        };
+        let mut redirects_left = client.max_redirects;
+
+        // once per redirect, every hop adds to the phases and bytes of the request
+        loop {
+            let (uri, method) = (req.uri().clone(), req.method().clone());
+            let sent_at = Instant::now();
+            let mut res = match client.send(req).await {
+                Ok(res) => res,
+                Err(e) => return (None, Some(client::classify(&e))),
+            };
+            let headers_at = Instant::now();
+            let status = res.status().as_u16();
+
+            let connection = res.extensions().get::<ConnectionInfo>().cloned();
+            let exchange = connection.as_ref().and_then(ConnectionInfo::exchange);
+
+            // connection setup only counts for the request that opened the connection
+            let (dns, connect, tls) = connection
+                .as_ref()
+                .and_then(ConnectionInfo::claim_setup)
+                .unwrap_or_default();
+            phases.dns += dns;
+            phases.connect += connect;
+            phases.tls += tls;
+            phases.ttfb += (headers_at - sent_at).saturating_sub(dns + connect + tls);
+
+            // read the whole body, the request isn't done until then and the
+            // connection can't go back into the pool before it either
+            let outcome = loop {
+                match res.body_mut().data().await {
+                    Some(Ok(_)) => {}
+                    None => break (Some(status), None),
+                    Some(Err(_)) => break (Some(status), Some(TransportError::BodyRead)),
                 }
+            };
+            phases.body += headers_at.elapsed();
+            if let (Some(connection), Some(exchange)) = (connection, exchange) {
+                let (sent, received) = connection.bytes(exchange);
+                bytes = (bytes.0 + sent, bytes.1 + received);
             }
-            Err(_) => Ok(Arc::new(Report {
-                succeeded: 0,
-                failed: 1,
-                total_requests: 1,
-                elapsed: 0,
-                transaction_rate: 0.0,
-                duration: start_of_request.elapsed(),
-            })),
+
+            let next = match outcome {
+                (_, None) if redirects_left > 0 => {
+                    spec.redirect(&uri, &method, status, res.headers())
+                }
+                _ => None,
+            };
+            match next {
+                Some(Ok(next)) => {
+                    req = next;
+                    redirects_left -= 1;
+                }
+                // e.g. a location that isn't a url
+                Some(Err(_)) => return (Some(status), Some(TransportError::Other)),
+                None => return outcome,
+            }
+        }
+    };
+
+    let timeout = async {
+        match spec.timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => futures::future::pending().await,
         }
     };
 
-    tokio::select! {
+    // None when the request was given up on
+    let outcome = tokio::select! {
         res = make_request => {
-            res
+            Some(res)
+        }
+        _ = timeout => {
+            None
         }
+    };
+    let finished_at = Instant::now();
+
+    let (status, transport_error) = outcome.unwrap_or((None, None));
+    // any 2xx, e.g. a 201 or 204 from an api that was posted to
+    let succeeded =
+        status.is_some_and(|status| (200..300).contains(&status)) && transport_error.is_none();
+
+    let mut transport_errors = TransportErrors::default();
+    if let Some(err) = transport_error {
+        transport_errors.record(err);
     }
+
+    Ok(Arc::new(Report {
+        succeeded: succeeded as i64,
+        failed: (outcome.is_some() && !succeeded) as i64,
+        timed_out: outcome.is_none() as i64,
+        total_requests: 1,
+        status,
+        transport_errors,
+        phases,
+        elapsed: 0,
+        bytes_sent: bytes.0,
+        bytes_received: bytes.1,
+        duration: finished_at - start_of_request,
+        corrected_duration: finished_at - scheduled_at,
+    }))
 }
@@ -0,0 +1,331 @@
+use crate::arrival::Arrival;
+use crate::histogram::LatencySummary;
+use crate::html;
+use crate::types::{millis, TransportErrors};
+use crate::{MachineDetails, RunResults, TestConfig, TestLength};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+use std::time::Duration;
+
+// bumped whenever a field is renamed or removed, adding one is fine
+const JSON_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    Json,
+    Html,
+}
+
+/// a report written once the run is over, `<format>=<path>` on the cli
+#[derive(Clone, Debug)]
+pub struct Output {
+    pub format: Format,
+    pub path: String,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    /// `json=<path>` or `html=<path>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected <format>=<path> e.g. json=run.json, got {:?}", s))?;
+        let format = match format {
+            "json" => Format::Json,
+            "html" => Format::Html,
+            _ => {
+                return Err(format!(
+                    "unknown output format {:?}, expected json or html",
+                    format
+                ))
+            }
+        };
+        if path.is_empty() {
+            return Err(format!("no path to write the {} output to", format));
+        }
+        Ok(Output {
+            format,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Json => f.write_str("json"),
+            Format::Html => f.write_str("html"),
+        }
+    }
+}
+
+impl Output {
+    pub fn write(&self, results: &RunResults, config: &TestConfig) -> Result<(), Box<dyn Error>> {
+        let contents = match self.format {
+            Format::Json => serde_json::to_vec_pretty(&JsonReport::new(results, config))?,
+            Format::Html => html::render(results, config).into_bytes(),
+        };
+        fs::write(&self.path, contents)
+            .map_err(|e| format!("could not write {} to {}: {}", self.format, self.path, e))?;
+        Ok(())
+    }
+}
+
+/// the json document, everything a dashboard could want to know about a run. latencies
+/// are in milliseconds
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    version: u32,
+    config: ReportConfig,
+    totals: JsonTotals<'a>,
+    latency: JsonLatency,
+    /// response count by status code
+    status_codes: BTreeMap<u16, u64>,
+    per_second: Vec<JsonSecond>,
+    /// the wireless link as the run ended
+    machine: MachineDetails,
+}
+
+/// the config of a run the way reports show it: durations in milliseconds, no header
+/// values and no body, since those tend to carry credentials. kept apart from the
+/// structs the run is driven by so changing those doesn't change the report
+#[derive(Serialize)]
+pub struct ReportConfig {
+    targets: Vec<ReportTarget>,
+    method: String,
+    /// names only
+    headers: Vec<String>,
+    /// None without a body
+    body_bytes: Option<usize>,
+    /// requests per second, None when the run followed stages
+    rate: Option<f64>,
+    stages: Vec<ReportStage>,
+    /// None when the run was -n requests long
+    duration_ms: Option<f64>,
+    requests: Option<u64>,
+    /// constant, poisson or burst
+    arrival: &'static str,
+    burst_on_ms: Option<f64>,
+    burst_off_ms: Option<f64>,
+    workers: u64,
+    seed: Option<u64>,
+    timeout_ms: Option<f64>,
+    keepalive: bool,
+    max_idle_per_host: Option<usize>,
+    idle_timeout_ms: Option<f64>,
+    tcp_nodelay: bool,
+    connect_timeout_ms: Option<f64>,
+    client_per_worker: bool,
+    max_redirects: usize,
+    percentiles: Vec<f64>,
+    significant_digits: u8,
+    window_ms: f64,
+    warmup_ms: f64,
+    apdex_t_ms: Option<f64>,
+    /// percent, e.g. 99.9
+    availability: Option<f64>,
+    thresholds: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ReportTarget {
+    url: String,
+    weight: u64,
+}
+
+/// the rate moves linearly from `from_rps` to `to_rps` over the stage
+#[derive(Serialize)]
+struct ReportStage {
+    from_rps: f64,
+    to_rps: f64,
+    duration_ms: f64,
+}
+
+impl ReportConfig {
+    pub fn new(config: &TestConfig) -> Self {
+        let (load, stats) = (&config.load, &config.stats);
+        let (arrival, burst_on, burst_off) = match load.arrival {
+            Arrival::Constant => ("constant", None, None),
+            Arrival::Poisson => ("poisson", None, None),
+            Arrival::Burst { on, off } => ("burst", Some(on), Some(off)),
+        };
+        let (duration, requests) = match load.test_length {
+            TestLength::Duration(duration) => (Some(duration), None),
+            TestLength::Requests(requests) => (None, Some(requests)),
+        };
+        let stages = load.profile.stages();
+
+        ReportConfig {
+            targets: load
+                .targets
+                .list()
+                .iter()
+                .map(|target| ReportTarget {
+                    url: target.url.clone(),
+                    weight: target.weight,
+                })
+                .collect(),
+            method: load.spec.method.to_string(),
+            headers: load
+                .spec
+                .headers
+                .keys()
+                .map(|name| name.to_string())
+                .collect(),
+            body_bytes: load.spec.body.as_ref().map(|body| body.len()),
+            // a fixed rate is a single stage that never ends
+            rate: (!load.profile.is_staged()).then(|| stages[0].to),
+            stages: if load.profile.is_staged() {
+                stages
+                    .iter()
+                    .map(|stage| ReportStage {
+                        from_rps: stage.from,
+                        to_rps: stage.to,
+                        duration_ms: millis(stage.duration),
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            duration_ms: duration.map(millis),
+            requests,
+            arrival,
+            burst_on_ms: burst_on.map(millis),
+            burst_off_ms: burst_off.map(millis),
+            workers: load.concurrent_clients,
+            seed: load.seed,
+            timeout_ms: load.spec.timeout.map(millis),
+            keepalive: load.client.keepalive,
+            max_idle_per_host: load.client.max_idle_per_host,
+            idle_timeout_ms: load.client.idle_timeout.map(millis),
+            tcp_nodelay: load.client.tcp_nodelay,
+            connect_timeout_ms: load.client.connect_timeout.map(millis),
+            client_per_worker: load.client.per_worker,
+            max_redirects: load.client.max_redirects,
+            percentiles: stats.percentiles.clone(),
+            significant_digits: stats.significant_digits,
+            window_ms: millis(stats.window),
+            warmup_ms: millis(stats.warmup),
+            apdex_t_ms: stats.apdex_t.map(millis),
+            availability: stats.availability,
+            thresholds: config.thresholds.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonTotals<'a> {
+    requests: i64,
+    succeeded: i64,
+    failed: i64,
+    timed_out: i64,
+    transport_errors: &'a TransportErrors,
+    bytes_sent: u64,
+    bytes_received: u64,
+    /// of the measured part of the run, warm-up excluded
+    elapsed_secs: f64,
+    rps: f64,
+    error_rate: Option<f64>,
+    completed: bool,
+}
+
+#[derive(Serialize)]
+struct JsonLatency {
+    min_ms: f64,
+    max_ms: f64,
+    mean_ms: f64,
+    stddev_ms: f64,
+    percentiles: Vec<JsonPercentile>,
+}
+
+#[derive(Serialize)]
+struct JsonPercentile {
+    percentile: f64,
+    ms: f64,
+}
+
+#[derive(Serialize)]
+struct JsonSecond {
+    /// since the start of the test
+    second: u64,
+    warmup: bool,
+    requests: u64,
+    errors: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    percentiles: Vec<JsonPercentile>,
+}
+
+impl<'a> JsonReport<'a> {
+    fn new(results: &'a RunResults, config: &'a TestConfig) -> Self {
+        let report = &results.report;
+        let per_second_bytes = results.throughput.per_second();
+
+        JsonReport {
+            version: JSON_VERSION,
+            config: ReportConfig::new(config),
+            totals: JsonTotals {
+                requests: report.total_requests,
+                succeeded: report.succeeded,
+                failed: report.failed,
+                timed_out: report.timed_out,
+                transport_errors: &report.transport_errors,
+                bytes_sent: report.bytes_sent,
+                bytes_received: report.bytes_received,
+                elapsed_secs: results.elapsed.as_secs_f64(),
+                rps: results.rps(),
+                error_rate: results.error_rate(),
+                completed: results.completed,
+            },
+            latency: JsonLatency::new(&results.latencies.summary(&config.stats.percentiles)),
+            status_codes: results.status_codes.by_code().collect(),
+            per_second: results
+                .timeline
+                .finished_seconds()
+                .iter()
+                .enumerate()
+                .map(|(second, stats)| {
+                    let (bytes_sent, bytes_received) =
+                        per_second_bytes.get(second).copied().unwrap_or_default();
+                    JsonSecond {
+                        second: second as u64,
+                        warmup: Duration::from_secs(second as u64) < results.warmup,
+                        requests: stats.requests,
+                        errors: stats.errors,
+                        bytes_sent,
+                        bytes_received,
+                        percentiles: percentiles(&stats.percentiles),
+                    }
+                })
+                .collect(),
+            machine: MachineDetails::probe(),
+        }
+    }
+}
+
+impl JsonLatency {
+    fn new(summary: &LatencySummary) -> Self {
+        JsonLatency {
+            min_ms: millis(summary.min),
+            max_ms: millis(summary.max),
+            mean_ms: millis(summary.mean),
+            stddev_ms: millis(summary.stddev),
+            percentiles: percentiles(&summary.percentiles),
+        }
+    }
+}
+
+fn percentiles(percentiles: &[(f64, Duration)]) -> Vec<JsonPercentile> {
+    percentiles
+        .iter()
+        .map(|&(percentile, latency)| JsonPercentile {
+            percentile,
+            ms: millis(latency),
+        })
+        .collect()
+}
@@ -0,0 +1,311 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::time::Duration;
+
+/// a stretch of the test during which the requested rate moves linearly from `from` to `to`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Stage {
+    pub from: f64,
+    pub to: f64,
+    pub duration: Duration,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let duration = humantime::format_duration(self.duration);
+        if self.from == self.to {
+            write!(f, "hold {} rps for {}", self.to, duration)
+        } else {
+            write!(f, "ramp {}→{} rps over {}", self.from, self.to, duration)
+        }
+    }
+}
+
+/// the requested rate over the course of a test, as a list of stages run back to back
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Profile {
+    stages: Vec<Stage>,
+    // true when the profile came from --stages rather than a fixed qps
+    staged: bool,
+}
+
+impl Profile {
+    /// one never ending stage at `qps`, used when no stages are given
+    pub fn constant(qps: f64) -> Self {
+        Profile {
+            stages: vec![Stage {
+                from: qps,
+                to: qps,
+                duration: Duration::MAX,
+            }],
+            staged: false,
+        }
+    }
+
+    /// parses a comma separated stage list, every stage being one of:
+    ///
+    /// * `ramp:<rps>:<duration>` ramps linearly from the previous stage's rate
+    ///   (0 for the first stage) up or down to `<rps>`
+    /// * `hold:<rps>:<duration>` jumps to `<rps>` and stays there
+    ///
+    /// e.g. `ramp:500:60s,hold:500:5m,hold:800:2m,ramp:0:1m`
+    pub fn parse(list: &str) -> Result<Self, Box<dyn Error>> {
+        Profile::from_stages(list.split(','))
+    }
+
+    /// same as `parse` but one stage per line, `#` comments and empty lines are skipped
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("could not read stages file {}: {}", path, e))?;
+        Profile::from_stages(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#')),
+        )
+    }
+
+    fn from_stages<'a>(raw_stages: impl Iterator<Item = &'a str>) -> Result<Self, Box<dyn Error>> {
+        let mut stages: Vec<Stage> = Vec::new();
+        for raw in raw_stages {
+            let fields = raw.trim().split(':').collect::<Vec<_>>();
+            if fields.len() != 3 {
+                return Err(format!(
+                    "stage {:?} is not in `ramp:<rps>:<duration>` or `hold:<rps>:<duration>` form",
+                    raw
+                )
+                .into());
+            }
+
+            let rate = fields[1]
+                .parse::<f64>()
+                .ok()
+                .filter(|r| r.is_finite() && *r >= 0.0)
+                .ok_or_else(|| format!("invalid rate in stage {:?}", raw))?;
+            let duration = humantime::parse_duration(fields[2])
+                .map_err(|e| format!("invalid duration in stage {:?}: {}", raw, e))?;
+            // the rate over a stage is undefined if it takes no time at all
+            if duration.is_zero() {
+                return Err(format!("stage {:?} has to last longer than 0", raw).into());
+            }
+
+            let from = match fields[0] {
+                "ramp" => stages.last().map(|s| s.to).unwrap_or(0.0),
+                "hold" => rate,
+                kind => return Err(format!("unknown stage kind {:?} in {:?}", kind, raw).into()),
+            };
+
+            stages.push(Stage {
+                from,
+                to: rate,
+                duration,
+            });
+        }
+
+        if stages.is_empty() {
+            return Err("no stages given".into());
+        }
+
+        Ok(Profile {
+            stages,
+            staged: true,
+        })
+    }
+
+    /// the same profile with every rate multiplied by `factor`, used to share a
+    /// profile out between several agents
+    pub fn scaled(&self, factor: f64) -> Self {
+        Profile {
+            stages: self
+                .stages
+                .iter()
+                .map(|stage| Stage {
+                    from: stage.from * factor,
+                    to: stage.to * factor,
+                    duration: stage.duration,
+                })
+                .collect(),
+            staged: self.staged,
+        }
+    }
+
+    pub fn is_staged(&self) -> bool {
+        self.staged
+    }
+
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// total length of all stages
+    pub fn duration(&self) -> Duration {
+        self.stages
+            .iter()
+            .fold(Duration::ZERO, |acc, s| acc.saturating_add(s.duration))
+    }
+
+    /// index and stage active `at` into the test, None once all stages are over
+    pub fn stage_at(&self, at: Duration) -> Option<(usize, &Stage)> {
+        let mut stage_start = Duration::ZERO;
+        for (indx, stage) in self.stages.iter().enumerate() {
+            let stage_end = stage_start.saturating_add(stage.duration);
+            if at < stage_end {
+                return Some((indx, stage));
+            }
+            stage_start = stage_end;
+        }
+        None
+    }
+
+    /// requests the profile asks for from the start until `at`, i.e. `∫ rate(t) dt
+    /// from 0 to at`
+    pub fn requests_until(&self, at: Duration) -> f64 {
+        let at = at.as_secs_f64();
+        let mut requests = 0.0;
+        let mut stage_start = 0.0;
+
+        for stage in &self.stages {
+            if at <= stage_start {
+                break;
+            }
+            let length = stage.duration.as_secs_f64();
+            // a zero length stage asks for nothing, the ones after it still count
+            if length == 0.0 {
+                continue;
+            }
+            let covered = (at - stage_start).min(length);
+            let slope = (stage.to - stage.from) / length;
+            requests += stage.from * covered + slope * covered * covered / 2.0;
+            stage_start += length;
+        }
+
+        requests
+    }
+
+    /// walks forward from `at` (seconds since the start) until `work` requests worth of
+    /// rate have been covered, i.e. solves `∫ rate(t) dt from at to x = work` for x.
+    /// None if the profile ends first.
+    pub fn advance(&self, at: f64, work: f64) -> Option<f64> {
+        let mut work = work;
+        let mut t = at;
+        let mut stage_start = 0.0;
+
+        for stage in &self.stages {
+            let stage_end = stage_start + stage.duration.as_secs_f64();
+            if t >= stage_end {
+                stage_start = stage_end;
+                continue;
+            }
+
+            // rate(x) = rate_t + slope * (x - t) for x inside this stage
+            let slope = (stage.to - stage.from) / stage.duration.as_secs_f64();
+            let rate_t = stage.from + slope * (t - stage_start);
+            let remaining = stage_end - t;
+
+            // area under the rate for what is left of this stage
+            let area = rate_t * remaining + slope * remaining * remaining / 2.0;
+            if area >= work {
+                // root of slope/2 dx² + rate_t dx - work = 0, written so that it
+                // stays stable for a flat or falling rate too
+                let dx = 2.0 * work / (rate_t + (rate_t * rate_t + 2.0 * slope * work).sqrt());
+                return Some(t + dx);
+            }
+
+            work -= area;
+            t = stage_end;
+            stage_start = stage_end;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn parse_ramps_from_the_previous_rate() {
+        let profile = Profile::parse("ramp:100:10s,hold:100:10s,ramp:0:5s").unwrap();
+        let stages = profile
+            .stages()
+            .iter()
+            .map(|s| (s.from, s.to, s.duration.as_secs()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stages,
+            [(0.0, 100.0, 10), (100.0, 100.0, 10), (100.0, 0.0, 5)]
+        );
+        assert!(profile.is_staged());
+        assert_eq!(profile.duration(), Duration::from_secs(25));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_stages() {
+        for list in [
+            "",
+            "ramp:100",
+            "jump:100:10s",
+            "hold:-1:10s",
+            "hold:NaN:10s",
+            "hold:100:soon",
+            "hold:100:10s,ramp:0:0s",
+        ] {
+            assert!(Profile::parse(list).is_err(), "{:?} was accepted", list);
+        }
+    }
+
+    #[test]
+    fn requests_until_integrates_the_rate() {
+        let profile = Profile::parse("ramp:100:10s,hold:100:10s,ramp:0:5s").unwrap();
+        let until = |secs| profile.requests_until(Duration::from_secs(secs));
+        assert!(close(until(0), 0.0));
+        assert!(close(until(5), 125.0));
+        assert!(close(until(10), 500.0));
+        assert!(close(until(20), 1500.0));
+        assert!(close(until(25), 1750.0));
+        // nothing more is asked for once the profile is over
+        assert!(close(until(60), 1750.0));
+    }
+
+    #[test]
+    fn advance_is_the_inverse_of_requests_until() {
+        let profile = Profile::parse("ramp:100:10s,hold:100:10s,ramp:0:5s").unwrap();
+        assert!(close(profile.advance(0.0, 125.0).unwrap(), 5.0));
+        assert!(close(profile.advance(5.0, 375.0).unwrap(), 10.0));
+        // across the end of a stage into the next one
+        assert!(close(profile.advance(9.0, 195.0).unwrap(), 11.0));
+        // on a falling rate
+        assert!(close(profile.advance(20.0, 160.0).unwrap(), 22.0));
+        assert_eq!(profile.advance(20.0, 251.0), None);
+
+        let constant = Profile::constant(10.0);
+        assert!(close(constant.advance(3.0, 1.0).unwrap(), 3.1));
+    }
+
+    #[test]
+    fn requests_until_skips_zero_length_stages() {
+        // --stages won't take one, but a profile can still end up with it
+        let stage = |from, to, secs| Stage {
+            from,
+            to,
+            duration: Duration::from_secs(secs),
+        };
+        let profile = Profile {
+            stages: vec![
+                stage(0.0, 100.0, 10),
+                stage(100.0, 100.0, 10),
+                stage(0.0, 0.0, 0),
+                stage(50.0, 50.0, 10),
+            ],
+            staged: true,
+        };
+        assert_eq!(profile.requests_until(Duration::from_secs(30)), 2000.0);
+    }
+}
@@ -0,0 +1,202 @@
+use bytes::Bytes;
+use hyper::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH,
+    CONTENT_TYPE, COOKIE, LOCATION, PROXY_AUTHORIZATION, TRANSFER_ENCODING,
+};
+use hyper::{Body, Method, Request, Uri};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fs;
+use std::time::Duration;
+use url::Url;
+
+/// everything about the request except the url, shared by all workers
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "RawSpec", try_from = "RawSpec")]
+pub struct RequestSpec {
+    pub method: Method,
+    pub headers: HeaderMap,
+    // Bytes so every request can send the body without copying it
+    pub body: Option<Bytes>,
+    /// give up on a request after this long and count it as timed out
+    pub timeout: Option<Duration>,
+}
+
+impl RequestSpec {
+    pub fn new(
+        method: Method,
+        raw_headers: &[String],
+        body: Option<String>,
+        body_file: Option<String>,
+        timeout: Option<Duration>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut headers = HeaderMap::new();
+        for raw in raw_headers {
+            let (name, value) = parse_header(raw)?;
+            headers.append(name, value);
+        }
+
+        let body = match (body, body_file) {
+            (Some(body), _) => Some(Bytes::from(body)),
+            (None, Some(path)) => {
+                Some(Bytes::from(fs::read(&path).map_err(|e| {
+                    format!("could not read body file {}: {}", path, e)
+                })?))
+            }
+            (None, None) => None,
+        };
+
+        Ok(RequestSpec {
+            method,
+            headers,
+            body,
+            timeout,
+        })
+    }
+
+    pub fn build(&self, url: &str) -> Result<Request<Body>, hyper::http::Error> {
+        let body = match &self.body {
+            Some(body) => Body::from(body.clone()),
+            None => Body::empty(),
+        };
+        let mut req = Request::builder()
+            .method(self.method.clone())
+            .uri(url)
+            .body(body)?;
+        req.headers_mut().extend(self.headers.clone());
+        Ok(req)
+    }
+
+    /// the request that follows a `status` response with `headers` to the previous
+    /// request `from` sent as `method`, None if it isn't a redirect. like browsers do a
+    /// 301, 302 or 303 turns into a GET without a body while a 307 or 308 goes out again
+    /// as it was, and credentials only go along to the same host
+    pub fn redirect(
+        &self,
+        from: &Uri,
+        method: &Method,
+        status: u16,
+        headers: &HeaderMap,
+    ) -> Option<Result<Request<Body>, Box<dyn Error>>> {
+        let keep_method = match status {
+            301..=303 => *method == Method::HEAD,
+            307 | 308 => true,
+            _ => return None,
+        };
+        let location = headers.get(LOCATION)?;
+        Some(self.follow(from, method, location, keep_method))
+    }
+
+    fn follow(
+        &self,
+        from: &Uri,
+        method: &Method,
+        location: &HeaderValue,
+        keep_method: bool,
+    ) -> Result<Request<Body>, Box<dyn Error>> {
+        let from = Url::parse(&from.to_string())?;
+        // relative to the url that answered with it
+        let to = from.join(location.to_str()?)?;
+
+        let mut headers = self.headers.clone();
+        if (from.scheme(), from.host_str(), from.port_or_known_default())
+            != (to.scheme(), to.host_str(), to.port_or_known_default())
+        {
+            for name in [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE] {
+                headers.remove(name);
+            }
+        }
+        // the body only stays as long as the method it was meant for does
+        let (method, body) = match &self.body {
+            Some(body) if keep_method && *method == self.method => {
+                (method.clone(), Body::from(body.clone()))
+            }
+            _ => {
+                for name in [
+                    CONTENT_TYPE,
+                    CONTENT_LENGTH,
+                    CONTENT_ENCODING,
+                    TRANSFER_ENCODING,
+                ] {
+                    headers.remove(name);
+                }
+                let method = if keep_method {
+                    method.clone()
+                } else {
+                    Method::GET
+                };
+                (method, Body::empty())
+            }
+        };
+
+        let mut req = Request::builder()
+            .method(method)
+            .uri(to.as_str())
+            .body(body)?;
+        *req.headers_mut() = headers;
+        Ok(req)
+    }
+}
+
+/// RequestSpec in a form serde can handle, headers as `Name: value` strings
+#[derive(Serialize, Deserialize)]
+struct RawSpec {
+    method: String,
+    headers: Vec<String>,
+    body: Option<Vec<u8>>,
+    timeout: Option<Duration>,
+}
+
+impl From<RequestSpec> for RawSpec {
+    fn from(spec: RequestSpec) -> Self {
+        RawSpec {
+            method: spec.method.to_string(),
+            headers: spec
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    format!("{}: {}", name, String::from_utf8_lossy(value.as_bytes()))
+                })
+                .collect(),
+            body: spec.body.map(|body| body.to_vec()),
+            timeout: spec.timeout,
+        }
+    }
+}
+
+impl TryFrom<RawSpec> for RequestSpec {
+    type Error = Box<dyn Error>;
+
+    fn try_from(raw: RawSpec) -> Result<Self, Self::Error> {
+        let mut spec = RequestSpec::new(
+            parse_method(&raw.method)?,
+            &raw.headers,
+            None,
+            None,
+            raw.timeout,
+        )?;
+        spec.body = raw.body.map(Bytes::from);
+        Ok(spec)
+    }
+}
+
+pub fn parse_method(raw: &str) -> Result<Method, String> {
+    raw.to_uppercase()
+        .parse::<Method>()
+        .map_err(|_| format!("invalid http method {:?}", raw))
+}
+
+/// parses a curl style `Name: value` header
+fn parse_header(raw: &str) -> Result<(HeaderName, HeaderValue), Box<dyn Error>> {
+    let (name, value) = raw
+        .split_once(':')
+        .ok_or_else(|| format!("header {:?} is not in `Name: value` form", raw))?;
+
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| format!("invalid header name in {:?}", raw))?;
+    let value = HeaderValue::from_str(value.trim())
+        .map_err(|_| format!("invalid header value in {:?}", raw))?;
+
+    Ok((name, value))
+}
@@ -0,0 +1,152 @@
+use crate::types::millis;
+use crate::Report;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    Csv,
+    JsonLines,
+}
+
+/// the task writing the log, Err if a record could not be written
+pub type Writer = JoinHandle<Result<(), String>>;
+
+/// where to log every request to, `<format>=<path>` on the cli
+#[derive(Clone, Debug)]
+pub struct RequestLog {
+    pub format: Format,
+    pub path: String,
+}
+
+impl FromStr for RequestLog {
+    type Err = String;
+
+    /// `csv=<path>` or `jsonl=<path>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = s.split_once('=').ok_or_else(|| {
+            format!(
+                "expected <format>=<path> e.g. csv=requests.csv, got {:?}",
+                s
+            )
+        })?;
+        let format = match format {
+            "csv" => Format::Csv,
+            "jsonl" => Format::JsonLines,
+            _ => {
+                return Err(format!(
+                    "unknown request log format {:?}, expected csv or jsonl",
+                    format
+                ))
+            }
+        };
+        if path.is_empty() {
+            return Err(format!("no path to write the {} request log to", format));
+        }
+        Ok(RequestLog {
+            format,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Csv => f.write_str("csv"),
+            Format::JsonLines => f.write_str("jsonl"),
+        }
+    }
+}
+
+/// one completed request. times are in milliseconds since the start of the test
+#[derive(Serialize)]
+pub struct RequestRecord {
+    scheduled_ms: f64,
+    /// later than scheduled when the request had to wait for a free worker
+    started_ms: f64,
+    /// coordinated omission corrected, from when the request was scheduled
+    latency_ms: f64,
+    status: Option<u16>,
+    /// success, http_error, timed_out or the transport error that ended it
+    outcome: &'static str,
+    bytes_sent: u64,
+    bytes_received: u64,
+    url: String,
+}
+
+impl RequestRecord {
+    /// `scheduled` is when the request was meant to go out, since the start of the test
+    pub fn new(report: &Report, scheduled: Duration, url: &str) -> Self {
+        let waited = report.corrected_duration.saturating_sub(report.duration);
+        let outcome = if report.timed_out > 0 {
+            "timed_out"
+        } else if let Some(kind) = report.transport_errors.kind() {
+            kind
+        } else if report.succeeded > 0 {
+            "success"
+        } else {
+            "http_error"
+        };
+        RequestRecord {
+            scheduled_ms: millis(scheduled),
+            started_ms: millis(scheduled + waited),
+            latency_ms: millis(report.corrected_duration),
+            status: report.status,
+            outcome,
+            bytes_sent: report.bytes_sent,
+            bytes_received: report.bytes_received,
+            url: url.to_string(),
+        }
+    }
+}
+
+impl RequestLog {
+    /// creates the log file and starts the task writing every record sent to it. the
+    /// channel never blocks a worker, the task ends once every sender has been dropped
+    /// and everything is flushed
+    pub fn spawn(&self) -> Result<(flume::Sender<RequestRecord>, Writer), String> {
+        let file = File::create(&self.path)
+            .map_err(|e| format!("could not create the request log {}: {}", self.path, e))?;
+        let (sender, receiver) = flume::unbounded::<RequestRecord>();
+        let (format, path) = (self.format, self.path.clone());
+
+        // plain blocking writes on a thread of their own, away from the workers and the tower
+        let writer = tokio::task::spawn_blocking(move || {
+            write_records(format, BufWriter::new(file), receiver)
+                .map_err(|e| format!("could not write the request log {}: {}", path, e))
+        });
+        Ok((sender, writer))
+    }
+}
+
+fn write_records(
+    format: Format,
+    file: BufWriter<File>,
+    receiver: flume::Receiver<RequestRecord>,
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(file);
+            for record in receiver.iter() {
+                writer.serialize(record)?;
+            }
+            writer.flush()?;
+        }
+        Format::JsonLines => {
+            let mut writer = file;
+            for record in receiver.iter() {
+                serde_json::to_writer(&mut writer, &record)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
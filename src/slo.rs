@@ -0,0 +1,185 @@
+use crate::Report;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// apdex split of the requests against a target latency T: satisfied up to T,
+/// tolerating up to 4T, frustrated beyond that or when the request failed
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Apdex {
+    pub target: Duration,
+    pub satisfied: u64,
+    pub tolerating: u64,
+    pub frustrated: u64,
+}
+
+impl Apdex {
+    pub fn new(target: Duration) -> Self {
+        Apdex {
+            target,
+            satisfied: 0,
+            tolerating: 0,
+            frustrated: 0,
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration, succeeded: bool) {
+        if !succeeded || latency > self.target * 4 {
+            self.frustrated += 1;
+        } else if latency > self.target {
+            self.tolerating += 1;
+        } else {
+            self.satisfied += 1;
+        }
+    }
+
+    /// adds the requests `other` split up against the same target
+    pub fn merge(&mut self, other: &Apdex) {
+        self.satisfied += other.satisfied;
+        self.tolerating += other.tolerating;
+        self.frustrated += other.frustrated;
+    }
+
+    /// (satisfied + tolerating / 2) / all, from 0 (everyone frustrated) to 1, None
+    /// before any request
+    pub fn score(&self) -> Option<f64> {
+        let total = self.satisfied + self.tolerating + self.frustrated;
+        if total == 0 {
+            None
+        } else {
+            Some((self.satisfied as f64 + self.tolerating as f64 / 2.0) / total as f64)
+        }
+    }
+}
+
+/// `0.654 (T 5ms): 157 satisfied, 1 tolerating, 83 frustrated`
+impl fmt::Display for Apdex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.score() {
+            Some(score) => write!(
+                f,
+                "{:.3} (T {}): {} satisfied, {} tolerating, {} frustrated",
+                score,
+                humantime::format_duration(self.target),
+                self.satisfied,
+                self.tolerating,
+                self.frustrated
+            ),
+            None => f.write_str("n/a, no requests"),
+        }
+    }
+}
+
+/// how much of the errors an availability target allows the run has used up
+#[derive(Clone, Copy)]
+pub struct ErrorBudget {
+    /// percentage of requests that have to succeed, e.g. 99.9
+    pub availability: f64,
+    /// errors the target allows over the requests sent so far
+    pub allowed: f64,
+    /// failed and timed out requests
+    pub spent: u64,
+}
+
+impl ErrorBudget {
+    pub fn new(availability: f64, report: &Report) -> Self {
+        ErrorBudget {
+            availability,
+            allowed: (1.0 - availability / 100.0) * report.total_requests as f64,
+            spent: (report.failed + report.timed_out) as u64,
+        }
+    }
+
+    /// share of the budget used up, above 1 once it is blown. None before any request
+    pub fn used(&self) -> Option<f64> {
+        if self.allowed > 0.0 {
+            Some(self.spent as f64 / self.allowed)
+        } else {
+            None
+        }
+    }
+}
+
+/// `12.5% used: 3 errors of 24.0 allowed at 99.9% availability`
+impl fmt::Display for ErrorBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.used() {
+            Some(used) => write!(
+                f,
+                "{:.1}% used: {} errors of {:.1} allowed at {}% availability",
+                used * 100.0,
+                self.spent,
+                self.allowed,
+                self.availability
+            ),
+            None => f.write_str("n/a, no requests"),
+        }
+    }
+}
+
+/// an availability target in percent, e.g. `99.9`
+pub fn parse_availability(raw: &str) -> Result<f64, String> {
+    raw.trim()
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .ok()
+        .filter(|percent| *percent > 0.0 && *percent < 100.0)
+        .ok_or_else(|| {
+            format!(
+                "availability has to be a percentage above 0 and below 100, got {:?}",
+                raw
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(total: i64, failed: i64, timed_out: i64) -> Report {
+        let mut report = Report::new();
+        report.total_requests = total;
+        report.failed = failed;
+        report.timed_out = timed_out;
+        report.succeeded = total - failed - timed_out;
+        report
+    }
+
+    #[test]
+    fn apdex_splits_at_t_and_4t() {
+        let mut apdex = Apdex::new(Duration::from_millis(100));
+        assert_eq!(apdex.score(), None);
+
+        apdex.record(Duration::from_millis(100), true);
+        apdex.record(Duration::from_millis(101), true);
+        apdex.record(Duration::from_millis(400), true);
+        apdex.record(Duration::from_millis(401), true);
+        // however fast, a failed request frustrates
+        apdex.record(Duration::from_millis(1), false);
+
+        assert_eq!(
+            (apdex.satisfied, apdex.tolerating, apdex.frustrated),
+            (1, 2, 2)
+        );
+        assert_eq!(apdex.score(), Some(0.4));
+    }
+
+    #[test]
+    fn error_budget_counts_failures_and_timeouts() {
+        let budget = ErrorBudget::new(99.0, &report(1000, 3, 2));
+        assert_eq!(budget.spent, 5);
+        assert!((budget.allowed - 10.0).abs() < 1e-9);
+        assert!((budget.used().unwrap() - 0.5).abs() < 1e-9);
+
+        let blown = ErrorBudget::new(99.0, &report(1000, 30, 0));
+        assert!(blown.used().unwrap() > 1.0);
+    }
+
+    #[test]
+    fn error_budget_without_requests_is_not_used() {
+        let budget = ErrorBudget::new(99.9, &report(0, 0, 0));
+        assert_eq!(budget.allowed, 0.0);
+        assert_eq!(budget.used(), None);
+        assert_eq!(budget.to_string(), "n/a, no requests");
+    }
+}
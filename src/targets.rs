@@ -0,0 +1,231 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fs;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Target {
+    pub url: String,
+    pub weight: u64,
+}
+
+/// list of urls to hit, each one picked with a probability proportional to its weight
+// only the list goes over the wire, the receiving end validates it again
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "Vec<Target>", try_from = "Vec<Target>")]
+pub struct Targets {
+    targets: Vec<Target>,
+    // running sum of weights, used to binary search a random point into the list
+    cumulative_weights: Vec<u64>,
+}
+
+impl Targets {
+    pub fn single(url: String) -> Result<Self, Box<dyn Error>> {
+        Targets::new(vec![Target { url, weight: 1 }])
+    }
+
+    fn new(targets: Vec<Target>) -> Result<Self, Box<dyn Error>> {
+        let mut total: u64 = 0;
+        let mut cumulative_weights = Vec::with_capacity(targets.len());
+        for target in &targets {
+            total = total
+                .checked_add(target.weight)
+                .ok_or("target weights add up to more than fits in 64 bits")?;
+            cumulative_weights.push(total);
+        }
+
+        if total == 0 {
+            return Err("no target with a non zero weight to load test".into());
+        }
+
+        for target in &targets {
+            let uri = target
+                .url
+                .parse::<hyper::Uri>()
+                .map_err(|e| format!("invalid url {:?}: {}", target.url, e))?;
+            if !matches!(uri.scheme_str(), Some("http") | Some("https")) || uri.host().is_none() {
+                return Err(format!("{:?} is not an http(s) url", target.url).into());
+            }
+        }
+
+        Ok(Targets {
+            targets,
+            cumulative_weights,
+        })
+    }
+
+    /// reads a targets file, one url per line optionally followed by a weight:
+    ///
+    /// ```text
+    /// # comments and empty lines are skipped
+    /// http://localhost:8080/users 8
+    /// http://localhost:8080/orders 2
+    /// http://localhost:8080/health
+    /// ```
+    ///
+    /// a url without a weight gets a weight of 1
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("could not read targets file {}: {}", path, e))?;
+
+        let mut targets = Vec::new();
+        for (line_no, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let url = fields.next().unwrap().to_string();
+            let weight = match fields.next() {
+                Some(w) => w
+                    .parse::<u64>()
+                    .map_err(|_| format!("{}:{}: invalid weight {:?}", path, line_no + 1, w))?,
+                None => 1,
+            };
+            if fields.next().is_some() {
+                return Err(format!(
+                    "{}:{}: expected `<url> [weight]`, got {:?}",
+                    path,
+                    line_no + 1,
+                    line
+                )
+                .into());
+            }
+
+            targets.push(Target { url, weight });
+        }
+
+        Targets::new(targets)
+    }
+
+    pub fn list(&self) -> &[Target] {
+        &self.targets
+    }
+
+    pub fn pick<R: Rng>(&self, rng: &mut R) -> &Target {
+        let total = *self.cumulative_weights.last().unwrap();
+        let point = rng.gen_range(0..total);
+        // first target whose running weight goes past the random point
+        let indx = self.cumulative_weights.partition_point(|&w| w <= point);
+        &self.targets[indx]
+    }
+}
+
+impl TryFrom<Vec<Target>> for Targets {
+    type Error = Box<dyn Error>;
+
+    fn try_from(targets: Vec<Target>) -> Result<Self, Self::Error> {
+        Targets::new(targets)
+    }
+}
+
+impl From<Targets> for Vec<Target> {
+    fn from(targets: Targets) -> Self {
+        targets.targets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::path::PathBuf;
+
+    /// writes `contents` to a targets file of its own for the test `name`
+    fn targets_file(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("xctl-targets-{}-{}.txt", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn from_file(name: &str, contents: &str) -> Result<Targets, Box<dyn Error>> {
+        let path = targets_file(name, contents);
+        let targets = Targets::from_file(path.to_str().unwrap());
+        fs::remove_file(path).unwrap();
+        targets
+    }
+
+    #[test]
+    fn from_file_reads_urls_and_weights() {
+        let targets = from_file(
+            "weights",
+            "# comments and empty lines are skipped\n\
+             \n\
+             http://localhost:8080/users 8\n\
+             \x20 http://localhost:8080/orders\t2 \n\
+             http://localhost:8080/health\n",
+        )
+        .unwrap();
+        let list = targets
+            .list()
+            .iter()
+            .map(|t| (t.url.as_str(), t.weight))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            list,
+            [
+                ("http://localhost:8080/users", 8),
+                ("http://localhost:8080/orders", 2),
+                ("http://localhost:8080/health", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_file_rejects_bad_lines() {
+        for (name, contents) in [
+            ("weight", "http://localhost/ lots\n"),
+            ("fields", "http://localhost/ 1 2\n"),
+            ("scheme", "ftp://localhost/ 1\n"),
+            ("zero", "http://localhost/a 0\nhttp://localhost/b 0\n"),
+            ("empty", "# nothing\n\n"),
+            (
+                "overflow",
+                "http://localhost/a 18446744073709551615\nhttp://localhost/b 1\n",
+            ),
+        ] {
+            assert!(
+                from_file(name, contents).is_err(),
+                "{:?} was accepted",
+                contents
+            );
+        }
+        assert!(Targets::from_file("/nonexistent/targets.txt").is_err());
+    }
+
+    #[test]
+    fn pick_follows_the_weights() {
+        let targets = Targets::new(vec![
+            Target {
+                url: "http://localhost/a".to_string(),
+                weight: 8,
+            },
+            Target {
+                url: "http://localhost/never".to_string(),
+                weight: 0,
+            },
+            Target {
+                url: "http://localhost/b".to_string(),
+                weight: 2,
+            },
+        ])
+        .unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut picked = [0; 3];
+        for _ in 0..10_000 {
+            let target = targets.pick(&mut rng);
+            let indx = targets
+                .list()
+                .iter()
+                .position(|t| t.url == target.url)
+                .unwrap();
+            picked[indx] += 1;
+        }
+        assert_eq!(picked[1], 0);
+        assert!((7_700..=8_300).contains(&picked[0]), "{:?}", picked);
+        assert_eq!(picked[0] + picked[2], 10_000);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::types::RunResults;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// what a threshold is checked against, all over the whole run
+#[derive(Clone, Copy, Debug)]
+pub enum Metric {
+    /// corrected latency at a percentile, e.g. 99 for `p99`
+    Percentile(f64),
+    Mean,
+    Max,
+    /// failed and timed out requests over all requests, 0 to 1
+    ErrorRate,
+    /// requests per second over the whole run
+    Rps,
+}
+
+impl Metric {
+    /// the metric for a finished run, in seconds for latencies. None when there was
+    /// nothing to measure it on
+    fn measure(&self, results: &RunResults) -> Option<f64> {
+        let summary = results.latencies.summary(&[]);
+        let latency = |value: Duration| {
+            if summary.count == 0 {
+                None
+            } else {
+                Some(value.as_secs_f64())
+            }
+        };
+        match *self {
+            Metric::Percentile(p) => latency(results.latencies.percentile(p)),
+            Metric::Mean => latency(summary.mean),
+            Metric::Max => latency(summary.max),
+            Metric::ErrorRate => results.error_rate(),
+            Metric::Rps => Some(results.rps()),
+        }
+    }
+
+    fn format(&self, value: f64) -> String {
+        match self {
+            Metric::Percentile(_) | Metric::Mean | Metric::Max => {
+                format!("{:.3}ms", value * 1000.0)
+            }
+            Metric::ErrorRate => format!("{:.3}%", value * 100.0),
+            Metric::Rps => format!("{:.1}", value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Comparison {
+    Below,
+    AtMost,
+    Above,
+    AtLeast,
+}
+
+/// a limit the run has to stay within, e.g. `p99<250ms`, `error_rate<0.5%` or `rps>400`
+#[derive(Clone, Debug)]
+pub struct Threshold {
+    raw: String,
+    metric: Metric,
+    comparison: Comparison,
+    // in the same unit `Metric::measure` gives
+    limit: f64,
+}
+
+/// how a finished run did against one threshold
+pub struct Verdict<'a> {
+    pub threshold: &'a Threshold,
+    /// None when the run had nothing to measure, which never passes
+    pub actual: Option<f64>,
+    pub passed: bool,
+}
+
+impl fmt::Display for Verdict<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let actual = match self.actual {
+            Some(actual) => self.threshold.metric.format(actual),
+            None => "nothing measured".to_string(),
+        };
+        let outcome = if self.passed { "passed" } else { "FAILED" };
+        write!(f, "{:<7}{} (got {})", outcome, self.threshold, actual)
+    }
+}
+
+impl Threshold {
+    pub fn check(&self, results: &RunResults) -> Verdict<'_> {
+        let actual = self.metric.measure(results);
+        let passed = actual.is_some_and(|actual| match self.comparison {
+            Comparison::Below => actual < self.limit,
+            Comparison::AtMost => actual <= self.limit,
+            Comparison::Above => actual > self.limit,
+            Comparison::AtLeast => actual >= self.limit,
+        });
+        Verdict {
+            threshold: self,
+            actual,
+            passed,
+        }
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    /// `<metric><op><limit>` where metric is `p<percentile>`, `mean`, `max`,
+    /// `error_rate` or `rps` and op is one of `<`, `<=`, `>`, `>=`. latencies take a
+    /// duration like `250ms`, error_rate a percentage like `0.5%` or a fraction
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let at = s
+            .find(['<', '>'])
+            .ok_or_else(|| format!("expected <metric><op><limit> e.g. p99<250ms, got {:?}", s))?;
+        let (name, rest) = s.split_at(at);
+        let (comparison, limit) = match (rest.starts_with('<'), rest[1..].strip_prefix('=')) {
+            (true, Some(limit)) => (Comparison::AtMost, limit),
+            (true, None) => (Comparison::Below, &rest[1..]),
+            (false, Some(limit)) => (Comparison::AtLeast, limit),
+            (false, None) => (Comparison::Above, &rest[1..]),
+        };
+        let (name, limit) = (name.trim(), limit.trim());
+
+        let metric = match name {
+            "mean" => Metric::Mean,
+            "max" => Metric::Max,
+            "error_rate" => Metric::ErrorRate,
+            "rps" => Metric::Rps,
+            _ => match name.strip_prefix('p').map(crate::histogram::parse_percentile) {
+                Some(Ok(p)) => Metric::Percentile(p),
+                Some(Err(e)) => return Err(e),
+                None => {
+                    return Err(format!(
+                        "unknown threshold metric {:?}, expected p<percentile>, mean, max, error_rate or rps",
+                        name
+                    ))
+                }
+            },
+        };
+
+        let limit = match metric {
+            Metric::Percentile(_) | Metric::Mean | Metric::Max => humantime::parse_duration(limit)
+                .map_err(|e| format!("bad latency limit {:?}: {}", limit, e))?
+                .as_secs_f64(),
+            Metric::ErrorRate => match limit.strip_suffix('%') {
+                Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+                None => limit.parse::<f64>(),
+            }
+            .map_err(|_| format!("bad error rate limit {:?}, expected e.g. 0.5%", limit))?,
+            Metric::Rps => limit
+                .parse::<f64>()
+                .map_err(|_| format!("bad rps limit {:?}", limit))?,
+        };
+
+        Ok(Threshold {
+            raw: s.to_string(),
+            metric,
+            comparison,
+            limit,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::histogram::LatencyHistogram;
+    use crate::types::{Report, StatusCodes, Throughput};
+    use crate::window::Timeline;
+
+    fn threshold(raw: &str) -> Threshold {
+        raw.parse().unwrap()
+    }
+
+    /// 100 requests over 10s, 2 of them failed, latencies of 1 to 10ms
+    fn results(latencies: impl Iterator<Item = u64>) -> RunResults {
+        let mut histogram = LatencyHistogram::new(3).unwrap();
+        for millis in latencies {
+            histogram.record(Duration::from_millis(millis));
+        }
+        let mut report = Report::new();
+        report.total_requests = 100;
+        report.succeeded = 98;
+        report.failed = 2;
+        RunResults {
+            report,
+            latencies: histogram,
+            uncorrected_latencies: None,
+            apdex: None,
+            status_codes: StatusCodes::new(),
+            error_budget: None,
+            timeline: Timeline::new(Duration::from_secs(10), 3, vec![]),
+            throughput: Throughput::new(),
+            warmup: Duration::ZERO,
+            elapsed: Duration::from_secs(10),
+            completed: true,
+        }
+    }
+
+    #[test]
+    fn from_str_reads_metric_op_and_limit() {
+        let p99 = threshold("p99<250ms");
+        assert!(matches!(p99.metric, Metric::Percentile(p) if p == 99.0));
+        assert!(matches!(p99.comparison, Comparison::Below));
+        assert_eq!(p99.limit, 0.25);
+
+        let p999 = threshold(" p99.9 <= 1s ");
+        assert!(matches!(p999.metric, Metric::Percentile(p) if p == 99.9));
+        assert!(matches!(p999.comparison, Comparison::AtMost));
+        assert_eq!(p999.limit, 1.0);
+        assert_eq!(p999.to_string(), "p99.9 <= 1s");
+
+        let errors = threshold("error_rate<0.5%");
+        assert!(matches!(errors.metric, Metric::ErrorRate));
+        assert_eq!(errors.limit, 0.005);
+        assert_eq!(threshold("error_rate<=0.01").limit, 0.01);
+
+        let rps = threshold("rps>=400");
+        assert!(matches!(rps.metric, Metric::Rps));
+        assert!(matches!(rps.comparison, Comparison::AtLeast));
+        assert_eq!(rps.limit, 400.0);
+        assert!(matches!(
+            threshold("mean>10ms").comparison,
+            Comparison::Above
+        ));
+    }
+
+    #[test]
+    fn from_str_rejects_bad_thresholds() {
+        for raw in [
+            "p99",
+            "latency<1s",
+            "p101<1s",
+            "p99<fast",
+            "error_rate<lots",
+            "rps>many",
+        ] {
+            assert!(raw.parse::<Threshold>().is_err(), "{:?} was accepted", raw);
+        }
+    }
+
+    #[test]
+    fn check_compares_the_run_against_the_limit() {
+        let results = results(1..=10);
+        for (raw, passed) in [
+            ("p50<10ms", true),
+            ("max<10ms", false),
+            ("mean<=6ms", true),
+            ("error_rate<5%", true),
+            ("error_rate<1%", false),
+            ("rps>=10", true),
+            ("rps>10", false),
+        ] {
+            assert_eq!(threshold(raw).check(&results).passed, passed, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn check_fails_when_nothing_was_measured() {
+        let p99 = threshold("p99<1s");
+        let verdict = p99.check(&results(0..0));
+        assert_eq!(verdict.actual, None);
+        assert!(!verdict.passed);
+    }
+}
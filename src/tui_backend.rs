@@ -1,13 +1,23 @@
+use crate::collector::{Collector, Record};
+use crate::histogram::{LatencyHistogram, LatencySummary};
+use crate::profile::Profile;
+use crate::slo::{Apdex, ErrorBudget};
+use crate::types::{format_latency, megabytes};
+use crate::window::WindowStats;
 use crate::MachineDetails;
+use crate::Phases;
 use crate::Report;
+use crate::RunResults;
+use crate::StatsConfig;
+use crate::StatusCodes;
+use crate::TestLength;
+use crate::Throughput;
 
 use crossterm::{
     event::{Event, KeyCode, KeyEvent, KeyModifiers},
     ExecutableCommand,
 };
-use netlink_wi::NlSocket;
 use std::error::Error;
-use std::io::Write;
 use std::io::{self, Stdout};
 use std::sync::Arc;
 use std::time::Duration;
@@ -18,7 +28,10 @@ use tui::layout::{Constraint, Corner, Direction, Layout};
 use tui::style::{Color, Modifier, Style};
 use tui::symbols;
 use tui::text::{Span, Spans};
-use tui::widgets::{Axis, BarChart, Block, Borders, Chart, Dataset, Gauge, List, ListItem};
+use tui::widgets::{
+    Axis, BarChart, Block, Borders, Chart, Dataset, Gauge, GraphType, List, ListItem, Paragraph,
+    Sparkline,
+};
 use tui::Terminal;
 
 enum Number {
@@ -26,29 +39,52 @@ enum Number {
     Float(f64),
 }
 
+/// percentiles of one request phase, over the requests that went through it
+struct PhaseStats {
+    name: &'static str,
+    p99: f64,
+    p95: f64,
+    p90: f64,
+    /// averaged over all requests, so connection setup shows up amortized
+    mean: f64,
+}
+
+/// achieved request rates, each next to the rate the profile asked for over the same time
+struct Rates {
+    overall: f64,
+    requested_overall: f64,
+    successful: f64,
+    /// over the last whole second
+    current: f64,
+    requested_current: f64,
+    current_errors: u64,
+}
+
 struct RequestWrapper<'a> {
     events: Vec<(&'a str, Number)>,
 }
 
 impl<'a> RequestWrapper<'a> {
-    fn new(totreq: Number, s: Number, f: Number, tr: Number) -> RequestWrapper<'a> {
+    fn new(totreq: Number, s: Number, f: Number, to: Number, sr: Number) -> RequestWrapper<'a> {
         RequestWrapper {
             events: vec![
                 ("Total Requests", totreq),
                 ("Succeeded", s),
                 ("Failed", f),
-                ("Transaction Rate", tr),
+                ("Timed Out", to),
+                ("Successful RPS", sr),
             ],
         }
     }
 }
 
-pub async fn write_to_t(
-    report: &mut Report,
-    report_receiver: &mut Receiver<Arc<Report>>,
+pub async fn write_to_t<R: Record>(
+    report_receiver: &mut Receiver<R>,
     test_started_at: Instant,
-    total_duration_for_test: Duration,
-) -> Result<(), Box<dyn Error>> {
+    test_length: TestLength,
+    profile: Arc<Profile>,
+    stats: &StatsConfig,
+) -> Result<RunResults, Box<dyn Error>> {
     crossterm::terminal::enable_raw_mode()?;
     io::stdout().execute(crossterm::terminal::EnterAlternateScreen)?;
     io::stdout().execute(crossterm::cursor::Hide)?;
@@ -61,132 +97,196 @@ pub async fn write_to_t(
         }
     };
 
-    let mut durations: Vec<std::time::Duration> = Vec::new();
+    let mut collector = Collector::new(test_started_at, stats);
     // terminal.clear()?;
 
     let mut p99_data: Vec<(f64, f64)> = Vec::new();
 
-    loop {
-        match report_receiver.recv().await {
-            Some(received_report) => {
-                report.add_report(
-                    received_report.succeeded,
-                    received_report.failed,
-                    received_report.total_requests,
-                    received_report.elapsed,
-                );
-
-                durations.push(received_report.duration);
-
-                report.transaction_rate =
-                    test_started_at.elapsed().as_secs_f64() / received_report.total_requests as f64;
-                let mut machine_details: MachineDetails = MachineDetails::new();
-
-                let socket = NlSocket::connect().unwrap();
-                let interfaces = socket.list_interfaces().unwrap();
-                for interface in interfaces {
-                    let interface = interface.unwrap();
-                    let stations = socket.list_stations(interface.interface_index).unwrap();
-                    for station in stations {
-                        let station = station.unwrap();
-                        // station.tx_bitrate.unwrap_or(0);
-                        machine_details.avg_signal = match station.average_signal {
-                            Some(v) => v,
-                            None => 0,
-                        };
-                        machine_details.rx_bitrate = match station.rx_bitrate {
-                            Some(v) => v.bitrate as f32 * 100.0 / 1000 as f32,
-                            None => 0.0,
-                        };
-                        machine_details.tx_bitrate = match station.tx_bitrate {
-                            Some(v) => v.bitrate as f32 * 100.0 / 1000 as f32,
-                            None => 0.0,
-                        };
-                        machine_details.frequency = match interface.frequency {
-                            Some(v) => v,
-                            None => 0,
-                        };
-                        machine_details.ssid = match interface.ssid {
-                            Some(ref v) => v.to_string(),
-                            None => 0.to_string(),
-                        };
-                    }
+    // the wireless link barely changes during a test, no need to ask for it on every report
+    let mut machine_details = MachineDetails::probe();
+    let mut machine_details_at = Instant::now();
+
+    // reports are taken in as fast as they come, the screen only redraws this often
+    let mut redraw = tokio::time::interval(Duration::from_millis(100));
+
+    // false when the run is stopped before every report is in
+    let completed = loop {
+        tokio::select! {
+            received = report_receiver.recv() => match received {
+                Some(received) => received.record_into(&mut collector),
+                // every sender is gone, so is the load
+                None => break true,
+            },
+            _ = redraw.tick() => {
+                let now = test_started_at.elapsed();
+
+                if machine_details_at.elapsed() >= Duration::from_secs(1) {
+                    machine_details = MachineDetails::probe();
+                    machine_details_at = Instant::now();
                 }
 
-                let mut dur_collection = durations
+                let measured_from = collector.measured_from(now);
+                let error_budget = collector.error_budget();
+                let requests_done = collector.requests_done;
+                let Collector {
+                    report,
+                    totals,
+                    throughput,
+                    timeline,
+                    ..
+                } = &mut collector;
+
+                let latency = totals.durations.summary(&stats.percentiles);
+                let uncorrected = if stats.show_uncorrected {
+                    Some(totals.uncorrected_durations.summary(&stats.percentiles))
+                } else {
+                    None
+                };
+
+                let window = timeline.window(now);
+
+                // what the load generator was asked to send until `at`, it stops at the
+                // end of the test even if the profile goes on
+                let requested_until = |at: Duration| match test_length {
+                    TestLength::Duration(d) => profile.requests_until(at.min(d)),
+                    TestLength::Requests(n) => profile.requests_until(at).min(n as f64),
+                };
+                let elapsed = (now - measured_from).as_secs_f64().max(f64::EPSILON);
+                let last_second = timeline.finished_seconds().len() as u64;
+                let rates = Rates {
+                    overall: report.total_requests as f64 / elapsed,
+                    requested_overall: (requested_until(now) - requested_until(measured_from))
+                        / elapsed,
+                    successful: report.succeeded as f64 / elapsed,
+                    current: timeline
+                        .finished_seconds()
+                        .last()
+                        .map(|second| second.requests as f64)
+                        .unwrap_or(0.0),
+                    requested_current: requested_until(Duration::from_secs(last_second))
+                        - requested_until(Duration::from_secs(last_second.saturating_sub(1))),
+                    current_errors: timeline
+                        .finished_seconds()
+                        .last()
+                        .map(|second| second.errors)
+                        .unwrap_or(0),
+                };
+                let window_latency = window.latencies.summary(&stats.percentiles);
+
+                let phase_stats = Phases::default()
+                    .named()
                     .iter()
-                    .map(|dur| dur.as_secs_f64())
+                    .enumerate()
+                    .map(|(indx, (name, _))| {
+                        let (p99, p95, p90) = top_percentiles(&totals.phase_durations[indx]);
+                        PhaseStats {
+                            name,
+                            p99,
+                            p95,
+                            p90,
+                            mean: totals.phase_totals[indx].as_secs_f64()
+                                / report.total_requests.max(1) as f64,
+                        }
+                    })
                     .collect::<Vec<_>>();
 
-                let (p99, p95, p90) = calculate_percentile(&mut dur_collection);
-
-                p99_data.append(&mut vec![(test_started_at.elapsed().as_secs_f64(), p99)]);
+                // the chart follows p99 over the window, so it moves when the target
+                // slows down instead of being averaged away by the whole test
+                let window_p99 = window.latencies.percentile(99.0).as_secs_f64() * 1000.0;
+                p99_data.push((now.as_secs_f64(), window_p99));
 
                 let p99data = p99_data.clone();
 
-                let x_elapsed = (test_started_at.elapsed().as_secs_f64()).trunc();
-                let y_offset = (p99 + 0.9).trunc();
+                let x_elapsed = now.as_secs_f64().trunc();
+                // some headroom above the highest point plotted so far
+                let y_offset = p99_data
+                    .iter()
+                    .fold(0.0_f64, |max, &(_, p99)| max.max(p99))
+                    * 1.1;
+                let y_offset = y_offset
+                    .ceil()
+                    .max(1.0);
 
                 draw(
                     &mut terminal,
                     report,
                     test_started_at,
-                    total_duration_for_test,
-                    machine_details,
-                    p99,
-                    p95,
-                    p90,
+                    test_length,
+                    stats.warmup,
+                    requests_done,
+                    &profile,
+                    &totals.status_codes,
+                    &machine_details,
+                    throughput,
+                    &latency,
+                    uncorrected.as_ref(),
+                    stats.window,
+                    &window,
+                    &window_latency,
+                    &rates,
+                    totals.apdex.as_ref(),
+                    error_budget.as_ref(),
+                    &phase_stats,
                     p99data,
                     x_elapsed,
                     y_offset,
-                    0,
                 )?;
-                // listen for keyboard event of ctrl+c
-                while crossterm::event::poll(std::time::Duration::from_secs(0))? {
-                    match crossterm::event::read()? {
-                        // User pressed q or ctrl-c
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Char('q'),
-                            ..
-                        })
-                        | Event::Key(KeyEvent {
-                            code: KeyCode::Char('c'),
-                            modifiers: KeyModifiers::CONTROL,
-                        }) => {
-                            std::io::stdout().execute(crossterm::terminal::LeaveAlternateScreen)?;
-                            crossterm::terminal::disable_raw_mode()?;
-                            std::io::stdout().execute(crossterm::cursor::Show)?;
-
-                            std::process::exit(libc::EXIT_SUCCESS);
-                        }
-                        _ => (),
-                    }
+                if stop_requested()? {
+                    break false;
                 }
             }
-            None => {
-                std::io::stdout().execute(crossterm::terminal::LeaveAlternateScreen)?;
-                crossterm::terminal::disable_raw_mode()?;
-                std::io::stdout().execute(crossterm::cursor::Show)?;
+        }
+    };
 
-                std::process::exit(libc::EXIT_SUCCESS);
-            }
+    std::io::stdout().execute(crossterm::terminal::LeaveAlternateScreen)?;
+    crossterm::terminal::disable_raw_mode()?;
+    std::io::stdout().execute(crossterm::cursor::Show)?;
+
+    Ok(collector.finish(completed))
+}
+
+/// true once the user pressed q or ctrl-c
+fn stop_requested() -> Result<bool, Box<dyn Error>> {
+    while crossterm::event::poll(Duration::from_secs(0))? {
+        match crossterm::event::read()? {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('q'),
+                ..
+            })
+            | Event::Key(KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: KeyModifiers::CONTROL,
+            }) => return Ok(true),
+            _ => (),
         }
     }
+    Ok(false)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn draw(
     terminal: &mut Terminal<CrosstermBackend<Stdout>>,
     report: &Report,
     start: Instant,
-    total_test_time: Duration,
-    machine_details: MachineDetails,
-    p99: f64,
-    p95: f64,
-    p90: f64,
+    test_length: TestLength,
+    warmup: Duration,
+    requests_done: u64,
+    profile: &Profile,
+    status_codes: &StatusCodes,
+    machine_details: &MachineDetails,
+    throughput: &Throughput,
+    latency: &LatencySummary,
+    uncorrected: Option<&LatencySummary>,
+    window_length: Duration,
+    window: &WindowStats,
+    window_latency: &LatencySummary,
+    rates: &Rates,
+    apdex: Option<&Apdex>,
+    error_budget: Option<&ErrorBudget>,
+    phase_stats: &[PhaseStats],
     p99_data: Vec<(f64, f64)>,
     x_elapsed: f64,
     y_axis_offset: f64,
-    total_reqs_to_hit: u64,
 ) -> Result<(), Box<dyn Error>> {
     terminal.draw(|f| {
         let row4 = Layout::default()
@@ -195,7 +295,8 @@ fn draw(
                 [
                     Constraint::Length(3),
                     Constraint::Length(8),
-                    Constraint::Length(10 as u16 + 2),
+                    Constraint::Length(11_u16 + 2),
+                    Constraint::Length(7),
                     Constraint::Percentage(40),
                 ]
                 .as_ref(),
@@ -204,39 +305,113 @@ fn draw(
 
         let now = std::time::Instant::now();
 
-        let gauge;
+        let window_label = humantime::format_duration(window_length).to_string();
 
-        if total_reqs_to_hit == 0 {
-            gauge = get_progress_by_duration(&now, &start, &total_test_time);
+        let active_stage = if profile.is_staged() {
+            profile.stage_at(now - start).map(|(indx, stage)| {
+                format!("stage {}/{}: {}", indx + 1, profile.stages().len(), stage)
+            })
         } else {
-            // todo
-            gauge =
-                get_progress_by_num_reqs(report.total_requests as u16, total_reqs_to_hit as u16);
+            None
+        };
+
+        match test_length {
+            TestLength::Duration(total_test_time) if !warmup.is_zero() => {
+                // warm-up gets its own stretch of the bar, as long as its share of the test
+                let block = Block::default().title("Progress").borders(Borders::ALL);
+                let total_ms = total_test_time.as_millis() as u32;
+                let warmup_ms = warmup.as_millis() as u32;
+                let parts = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints(
+                        [
+                            Constraint::Ratio(warmup_ms, total_ms),
+                            Constraint::Ratio(total_ms - warmup_ms, total_ms),
+                        ]
+                        .as_ref(),
+                    )
+                    .split(block.inner(row4[0]));
+                f.render_widget(block, row4[0]);
+
+                let warmup_gauge = Gauge::default()
+                    .gauge_style(Style::default().fg(Color::DarkGray))
+                    .label(Span::raw("warm-up"))
+                    .ratio(((now - start).as_secs_f64() / warmup.as_secs_f64()).clamp(0.0, 1.0));
+                f.render_widget(warmup_gauge, parts[0]);
+                let gauge = get_progress_by_duration(
+                    now,
+                    start + warmup,
+                    total_test_time - warmup,
+                    active_stage.as_deref(),
+                )
+                .block(Block::default());
+                f.render_widget(gauge, parts[1]);
+            }
+            TestLength::Duration(total_test_time) => {
+                let gauge =
+                    get_progress_by_duration(now, start, total_test_time, active_stage.as_deref());
+                f.render_widget(gauge, row4[0]);
+            }
+            // --warmup is a stretch of time, it can't be combined with -n
+            TestLength::Requests(total_reqs_to_hit) => {
+                let gauge = get_progress_by_num_reqs(requests_done, total_reqs_to_hit);
+                f.render_widget(gauge, row4[0]);
+            }
         }
 
-        f.render_widget(gauge, row4[0]);
-
         let mid = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .constraints(
+                [
+                    Constraint::Percentage(40),
+                    Constraint::Percentage(30),
+                    Constraint::Percentage(30),
+                ]
+                .as_ref(),
+            )
             .split(row4[1]);
 
         let bottom = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .constraints(
+                [
+                    Constraint::Ratio(1, 3),
+                    Constraint::Ratio(1, 3),
+                    Constraint::Ratio(1, 3),
+                ]
+                .as_ref(),
+            )
             .split(row4[2]);
-        let err_code_data: Vec<(&str, u64)> = vec![("s1", 110), ("s1", 1010)];
+        // one bar per exact status code, the per class totals go in the title
+        let status_labels: Vec<(String, u64)> = status_codes
+            .by_code()
+            .map(|(code, count)| (code.to_string(), count))
+            .collect();
+        let err_code_data: Vec<(&str, u64)> = status_labels
+            .iter()
+            .map(|(code, count)| (code.as_str(), *count))
+            .collect();
+
+        let status_classes = status_codes
+            .by_class()
+            .iter()
+            .map(|(class, count)| format!("{}: {}", class, count))
+            .collect::<Vec<_>>()
+            .join(" ");
 
-        let err_histo_width = 7;
+        // shrink the bars when there are more codes than fit at full width
+        let err_histo_width = (mid[0].width.saturating_sub(2) / err_code_data.len().max(1) as u16)
+            .saturating_sub(1)
+            .clamp(3, 7);
 
         let err_code_bar_chart = BarChart::default()
             .block(
                 Block::default()
-                    .title("Error Code Distribution")
+                    .title(format!("Status Code Distribution  {}", status_classes))
                     .borders(Borders::ALL),
             )
             .data(err_code_data.as_slice())
-            .bar_width(err_histo_width as u16);
+            .bar_width(err_histo_width);
 
         f.render_widget(err_code_bar_chart, mid[0]);
 
@@ -292,16 +467,151 @@ fn draw(
 
         f.render_widget(machine_details_list, mid[1]);
 
+        let elapsed = now - start;
+        let (sent_rate, received_rate) = throughput.last_second(elapsed);
+        let secs = elapsed.as_secs_f64().max(1.0);
+        let mean_sent_rate = megabytes(report.bytes_sent) / secs;
+        let mean_received_rate = megabytes(report.bytes_received) / secs;
+
+        // share of the wireless link's bitrate (Mb/s) the test is using
+        let link_usage = |rate: f64, bitrate: f32| {
+            if bitrate > 0.0 {
+                format!("{:.1}%", rate * 8.0 / bitrate as f64 * 100.0)
+            } else {
+                "n/a".to_string()
+            }
+        };
+
+        let throughput_lines = vec![
+            Spans::from(vec![Span::styled(
+                format!("Sent : {:.2} MB/s (avg {:.2})", sent_rate, mean_sent_rate),
+                Style::default().fg(Color::Yellow),
+            )]),
+            Spans::from(vec![Span::styled(
+                format!(
+                    "Received : {:.2} MB/s (avg {:.2})",
+                    received_rate, mean_received_rate
+                ),
+                Style::default().fg(Color::Green),
+            )]),
+            Spans::from(vec![Span::styled(
+                format!(
+                    "Total : {:.1} MB out / {:.1} MB in",
+                    megabytes(report.bytes_sent),
+                    megabytes(report.bytes_received)
+                ),
+                Style::default().fg(Color::Blue),
+            )]),
+            Spans::from(vec![Span::styled(
+                format!(
+                    "Link Usage : tx {} rx {}",
+                    link_usage(sent_rate, machine_details.tx_bitrate),
+                    link_usage(received_rate, machine_details.rx_bitrate)
+                ),
+                Style::default().fg(Color::Cyan),
+            )]),
+        ];
+
+        let throughput_block = Block::default().borders(Borders::ALL).title("Throughput");
+        let throughput_area = throughput_block.inner(mid[2]);
+        f.render_widget(throughput_block, mid[2]);
+
+        let throughput_rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(4), Constraint::Min(0)].as_ref())
+            .split(throughput_area);
+
+        f.render_widget(Paragraph::new(throughput_lines), throughput_rows[0]);
+
+        // received bytes of the most recent whole seconds that fit, in kB so
+        // small responses don't flatten to nothing
+        let finished_seconds = &throughput.per_second()[..throughput
+            .per_second()
+            .len()
+            .min(elapsed.as_secs() as usize)];
+        let received_history = finished_seconds
+            .iter()
+            .rev()
+            .take(throughput_rows[1].width as usize)
+            .rev()
+            .map(|(_, received)| received / 1000)
+            .collect::<Vec<_>>();
+
+        let received_sparkline = Sparkline::default()
+            .data(&received_history)
+            .style(Style::default().fg(Color::Green));
+
+        f.render_widget(received_sparkline, throughput_rows[1]);
+
         let bottomest = Layout::default()
             .direction(Direction::Horizontal)
             .constraints([Constraint::Percentage(100)].as_ref())
-            .split(row4[3]);
-
-        let datasets = vec![Dataset::default()
-            .name("data")
-            .marker(symbols::Marker::Dot)
-            .style(Style::default().fg(Color::Cyan))
-            .data(&p99_data)];
+            .split(row4[4]);
+
+        // a vertical line where each stage that has started so far begins
+        let stage_boundaries: Vec<Vec<(f64, f64)>> = if profile.is_staged() {
+            profile
+                .stages()
+                .iter()
+                .scan(0.0, |stage_start, stage| {
+                    let boundary = *stage_start;
+                    *stage_start += stage.duration.as_secs_f64();
+                    Some(boundary)
+                })
+                .skip(1)
+                .take_while(|&boundary| boundary <= x_elapsed)
+                .map(|boundary| vec![(boundary, 0.0), (boundary, y_axis_offset)])
+                .collect()
+        } else {
+            vec![]
+        };
+
+        // warm-up shaded by zigzagging a line across it, as tight as the braille dots go
+        let shade_end = warmup.as_secs_f64().min(x_elapsed);
+        let warmup_shade: Vec<(f64, f64)> = if shade_end > 0.0 {
+            let steps = (bottomest[0].width as f64 * 2.0 * shade_end / x_elapsed).ceil() as usize;
+            (0..=steps)
+                .flat_map(|step| {
+                    let x = shade_end * step as f64 / steps as f64;
+                    if step % 2 == 0 {
+                        [(x, 0.0), (x, y_axis_offset)]
+                    } else {
+                        [(x, y_axis_offset), (x, 0.0)]
+                    }
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        // the shade goes first so the data is drawn over it
+        let mut datasets = Vec::new();
+        if !warmup_shade.is_empty() {
+            datasets.push(
+                Dataset::default()
+                    .name("warm-up")
+                    .marker(symbols::Marker::Braille)
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(Color::DarkGray))
+                    .data(&warmup_shade),
+            );
+        }
+        datasets.push(
+            Dataset::default()
+                .name("data")
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(Color::Cyan))
+                .data(&p99_data),
+        );
+        for boundary in &stage_boundaries {
+            datasets.push(
+                Dataset::default()
+                    .marker(symbols::Marker::Braille)
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(Color::DarkGray))
+                    .data(boundary),
+            );
+        }
 
         // let _ = writefile(p99_data.clone());
 
@@ -309,7 +619,7 @@ fn draw(
             .block(
                 Block::default()
                     .title(Span::styled(
-                        "Chart 3",
+                        active_stage.as_deref().unwrap_or("Chart 3"),
                         Style::default()
                             .fg(Color::Cyan)
                             .add_modifier(Modifier::BOLD),
@@ -332,7 +642,7 @@ fn draw(
             )
             .y_axis(
                 Axis::default()
-                    .title("p99 latency")
+                    .title(format!("p99 ms, last {}", window_label))
                     .style(Style::default().fg(Color::Gray))
                     .bounds([0.0, y_axis_offset])
                     .labels(vec![
@@ -351,61 +661,195 @@ fn draw(
             Number::Int(report.total_requests),
             Number::Int(report.succeeded),
             Number::Int(report.failed),
-            Number::Float(report.transaction_rate),
+            Number::Int(report.timed_out),
+            Number::Float(rates.successful),
         );
 
-        let percentiles_floats: Vec<(&str, f64)> = vec![("p99", p99), ("p95", p95), ("p90", p90)];
-
-        let latency_data: Vec<ListItem> = percentiles_floats
-            .iter()
-            .map(|&(p, value)| {
-                let s = match p {
-                    "p99" => Style::default().fg(Color::Cyan),
-                    "p95" => Style::default().fg(Color::LightRed),
-                    "p90" => Style::default().fg(Color::Green),
-                    _ => Style::default(),
-                };
-                let header;
-
-                header = Spans::from(vec![Span::styled(format!("{} : {:<9}", p, value), s)]);
-
-                ListItem::new(vec![header])
-            })
-            .collect();
+        let percentile_colors = [
+            Color::Green,
+            Color::LightRed,
+            Color::Cyan,
+            Color::Yellow,
+            Color::Magenta,
+            Color::Blue,
+        ];
+        // (label, latency, latency over the window, uncorrected latency, style) for
+        // every row of the panel
+        let mut latency_rows = vec![(
+            "min".to_string(),
+            latency.min,
+            window_latency.min,
+            uncorrected.map(|u| u.min),
+            Style::default(),
+        )];
+        for (indx, (percentile, value)) in latency.percentiles.iter().enumerate() {
+            latency_rows.push((
+                format!("p{}", percentile),
+                *value,
+                window_latency.percentiles[indx].1,
+                uncorrected.map(|u| u.percentiles[indx].1),
+                Style::default().fg(percentile_colors[indx % percentile_colors.len()]),
+            ));
+        }
+        latency_rows.push((
+            "max".to_string(),
+            latency.max,
+            window_latency.max,
+            uncorrected.map(|u| u.max),
+            Style::default(),
+        ));
+        latency_rows.push((
+            "mean".to_string(),
+            latency.mean,
+            window_latency.mean,
+            uncorrected.map(|u| u.mean),
+            Style::default().fg(Color::Gray),
+        ));
+        latency_rows.push((
+            "stddev".to_string(),
+            latency.stddev,
+            window_latency.stddev,
+            uncorrected.map(|u| u.stddev),
+            Style::default().fg(Color::Gray),
+        ));
+
+        let mut latency_data: Vec<ListItem> =
+            vec![ListItem::new(vec![Spans::from(vec![Span::styled(
+                format!(
+                    "{:<7}{:>11}{:>11}{:>12}",
+                    "",
+                    "total",
+                    format!("last {}", window_label),
+                    if uncorrected.is_some() {
+                        "uncorrected"
+                    } else {
+                        ""
+                    }
+                ),
+                Style::default().add_modifier(Modifier::BOLD),
+            )])])];
+        for (label, value, window_value, uncorrected_value, style) in &latency_rows {
+            let text = format!(
+                "{:<7}{:>11}{:>11}{:>12}",
+                label,
+                format_latency(*value),
+                format_latency(*window_value),
+                uncorrected_value.map(format_latency).unwrap_or_default()
+            );
+            latency_data.push(ListItem::new(vec![Spans::from(vec![Span::styled(
+                text, *style,
+            )])]));
+        }
 
         let latency_list = List::new(latency_data)
-            .block(Block::default().borders(Borders::ALL).title("Latency Data"))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Latency Data ({} requests)", latency.count)),
+            )
             .start_corner(Corner::TopLeft);
 
         f.render_widget(latency_list, bottom[1]);
 
-        let events: Vec<ListItem> = request_tuple
+        let mut events: Vec<ListItem> = request_tuple
             .events
             .iter()
             .map(|(kpi, value)| {
-                let s = match kpi {
-                    &"Total Requests" => Style::default().fg(Color::Green),
-                    &"Succeeded" => Style::default().fg(Color::Magenta),
-                    &"Failed" => Style::default().fg(Color::Red),
-                    &"Transaction Rate" => Style::default().fg(Color::Blue),
+                let s = match *kpi {
+                    "Total Requests" => Style::default().fg(Color::Green),
+                    "Succeeded" => Style::default().fg(Color::Magenta),
+                    "Failed" => Style::default().fg(Color::Red),
+                    "Timed Out" => Style::default().fg(Color::Yellow),
+                    "Successful RPS" => Style::default().fg(Color::Blue),
                     _ => Style::default(),
                 };
 
-                let header;
-
-                match value {
+                let header = match value {
                     Number::Int(v) => {
-                        header = Spans::from(vec![Span::styled(format!("{} : {:<9}", kpi, v), s)]);
+                        Spans::from(vec![Span::styled(format!("{} : {:<9}", kpi, v), s)])
                     }
                     Number::Float(v) => {
-                        header = Spans::from(vec![Span::styled(format!("{} : {:<9}", kpi, v), s)]);
+                        Spans::from(vec![Span::styled(format!("{} : {:<9.1}", kpi, v), s)])
                     }
-                }
+                };
 
                 ListItem::new(vec![header])
             })
             .collect();
 
+        // red as soon as the requests going out fall behind what was asked for
+        let keeping_up = |achieved: f64, requested: f64| {
+            if achieved < requested * 0.9 {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default().fg(Color::Green)
+            }
+        };
+        events.push(ListItem::new(vec![Spans::from(vec![Span::styled(
+            format!(
+                "Overall RPS : {:.1} of {:.1} requested",
+                rates.overall, rates.requested_overall
+            ),
+            keeping_up(rates.overall, rates.requested_overall),
+        )])]));
+        events.push(ListItem::new(vec![Spans::from(vec![Span::styled(
+            format!(
+                "Current RPS : {:.0} of {:.1} requested",
+                rates.current, rates.requested_current
+            ),
+            keeping_up(rates.current, rates.requested_current),
+        )])]));
+        events.push(ListItem::new(vec![Spans::from(vec![Span::styled(
+            format!("Errors Last Second : {}", rates.current_errors),
+            Style::default().fg(Color::Cyan),
+        )])]));
+        // the same counts over just the most recent part of the test
+        events.push(ListItem::new(vec![Spans::from(vec![Span::styled(
+            format!(
+                "Last {} : {:.1} rps, {:.2}% errors",
+                window_label,
+                window.requests_per_sec,
+                window.error_rate * 100.0
+            ),
+            Style::default().fg(Color::Cyan),
+        )])]));
+        if let Some(apdex) = apdex {
+            let score = apdex.score().unwrap_or(1.0);
+            let s = if score >= 0.85 {
+                Style::default().fg(Color::Green)
+            } else if score >= 0.7 {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default().fg(Color::Red)
+            };
+            events.push(ListItem::new(vec![Spans::from(vec![Span::styled(
+                format!(
+                    "Apdex (T {}) : {:.3}",
+                    humantime::format_duration(apdex.target),
+                    score
+                ),
+                s,
+            )])]));
+        }
+        if let Some(budget) = error_budget {
+            let used = budget.used().unwrap_or(0.0);
+            let s = if used > 1.0 {
+                Style::default().fg(Color::Red)
+            } else if used > 0.5 {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default().fg(Color::Green)
+            };
+            events.push(ListItem::new(vec![Spans::from(vec![Span::styled(
+                format!(
+                    "Error Budget ({}%) : {:.1}% used",
+                    budget.availability,
+                    used * 100.0
+                ),
+                s,
+            )])]));
+        }
+
         let events_list = List::new(events)
             .block(
                 Block::default()
@@ -415,56 +859,135 @@ fn draw(
             .start_corner(Corner::TopLeft);
 
         f.render_widget(events_list, bottom[0]);
+
+        let transport_errors: Vec<ListItem> = report
+            .transport_errors
+            .counts()
+            .iter()
+            .map(|(kind, count)| {
+                let s = if *count > 0 {
+                    Style::default().fg(Color::Red)
+                } else {
+                    Style::default().fg(Color::DarkGray)
+                };
+                ListItem::new(vec![Spans::from(vec![Span::styled(
+                    format!("{} : {:<9}", kind, count),
+                    s,
+                )])])
+            })
+            .collect();
+
+        let transport_errors_list = List::new(transport_errors)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Transport Errors"),
+            )
+            .start_corner(Corner::TopLeft);
+
+        f.render_widget(transport_errors_list, bottom[2]);
+
+        let phases_row = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(row4[3]);
+
+        let phase_colors = [
+            Color::Magenta,
+            Color::Yellow,
+            Color::Red,
+            Color::Cyan,
+            Color::Green,
+        ];
+
+        let phase_items: Vec<ListItem> = phase_stats
+            .iter()
+            .zip(phase_colors.iter())
+            .map(|(phase, color)| {
+                ListItem::new(vec![Spans::from(vec![Span::styled(
+                    format!(
+                        "{:<8}: p99 {:<12.6} p95 {:<12.6} p90 {:<12.6}",
+                        phase.name, phase.p99, phase.p95, phase.p90
+                    ),
+                    Style::default().fg(*color),
+                )])])
+            })
+            .collect();
+
+        let phase_list = List::new(phase_items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Request Phases (dns/connect/tls over new connections)"),
+            )
+            .start_corner(Corner::TopLeft);
+
+        f.render_widget(phase_list, phases_row[0]);
+
+        // one bar, split by how much of the average request each phase takes up
+        let bar_width = phases_row[1].width.saturating_sub(2) as f64;
+        let total_mean: f64 = phase_stats.iter().map(|phase| phase.mean).sum();
+        let mut stacked_bar = Vec::new();
+        let mut legend = Vec::new();
+        for (phase, color) in phase_stats.iter().zip(phase_colors.iter()) {
+            let share = if total_mean > 0.0 {
+                phase.mean / total_mean
+            } else {
+                0.0
+            };
+            stacked_bar.push(Span::styled(
+                "█".repeat((share * bar_width).round() as usize),
+                Style::default().fg(*color),
+            ));
+            legend.push(Span::styled(
+                format!("{} {:.2}ms ", phase.name, phase.mean * 1000.0),
+                Style::default().fg(*color),
+            ));
+        }
+
+        let breakdown = Paragraph::new(vec![
+            Spans::from(stacked_bar.clone()),
+            Spans::from(stacked_bar),
+            Spans::from(vec![]),
+            Spans::from(legend),
+        ])
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Mean Request Breakdown"),
+        );
+
+        f.render_widget(breakdown, phases_row[1]);
     })?;
 
     Ok(())
 }
 
-fn calculate_percentile(data: &mut Vec<f64>) -> (f64, f64, f64) {
-    let (mut p99, mut p95, mut p90) = (0.0, 0.0, 0.0);
-
-    let percentil = 100;
-    // ascending sort
-    data.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    data.retain(|x| *x != 0.0);
-    let data_len = data.len();
-
-    // rank of highest duration
-    let mut rank = (percentil as f32 / 100.0 * data_len as f32) as usize;
-    // index of sorted array
-    let mut max_indx = data_len as usize - 1 as usize;
-
-    // calc percentiles and only match on the ones we are currently interested in
-    while max_indx != 0 {
-        let percentile = (rank as f32 / data_len as f32 * 100.0) as u8;
-        rank = rank - 1;
-        // println!("{}", data[max_indx - 1]);
-        match percentile {
-            99 => p99 = data[max_indx - 1],
-            95 => p95 = data[max_indx - 1],
-            90 => p90 = data[max_indx - 1],
-            _ => {}
-        }
-        max_indx -= 1;
-    }
-
-    (p99, p95, p90)
+/// (p99, p95, p90) in seconds
+fn top_percentiles(histogram: &LatencyHistogram) -> (f64, f64, f64) {
+    (
+        histogram.percentile(99.0).as_secs_f64(),
+        histogram.percentile(95.0).as_secs_f64(),
+        histogram.percentile(90.0).as_secs_f64(),
+    )
 }
 
 fn get_progress_by_duration<'a>(
-    now: &'a Instant,
-    start: &'a Instant,
-    total_test_time: &'a Duration,
+    now: Instant,
+    start: Instant,
+    total_test_time: Duration,
+    active_stage: Option<&str>,
 ) -> Gauge<'a> {
-    let progress = ((*now - *start).as_secs_f64() / total_test_time.as_secs_f64())
-        .max(0.0)
-        .min(1.0);
+    // `start` is in the future while warming up
+    let elapsed = now.saturating_duration_since(start);
+    let progress = (elapsed.as_secs_f64() / total_test_time.as_secs_f64()).clamp(0.0, 1.0);
 
-    let t = Duration::from(std::time::Duration::from_secs(
-        (*now - *start).as_secs_f64() as u64,
-    ));
+    let t = Duration::from_secs(elapsed.as_secs());
 
-    let gauge_label = format!("{:?} / {:?}", t, Duration::from(*total_test_time));
+    let mut gauge_label = format!("{:?} / {:?}", t, total_test_time);
+    if let Some(stage) = active_stage {
+        gauge_label = format!("{}  {}", gauge_label, stage);
+    }
 
     let gauge = Gauge::default()
         .block(Block::default().title("Progress").borders(Borders::ALL))
@@ -474,14 +997,24 @@ fn get_progress_by_duration<'a>(
     gauge
 }
 
-fn get_progress_by_num_reqs<'a>(req_count_completed: u16, total_reqs_to_hit: u16) -> Gauge<'a> {
-    let progress = req_count_completed / total_reqs_to_hit;
-    let gauge_label = format!("{:?} / 100 %", progress);
+fn get_progress_by_num_reqs<'a>(req_count_completed: u64, total_reqs_to_hit: u64) -> Gauge<'a> {
+    // ratio in f64 so that neither the division truncates nor huge counts overflow
+    let progress = if total_reqs_to_hit == 0 {
+        1.0
+    } else {
+        (req_count_completed as f64 / total_reqs_to_hit as f64).clamp(0.0, 1.0)
+    };
+    let gauge_label = format!(
+        "{} / {} requests ({:.1} %)",
+        req_count_completed,
+        total_reqs_to_hit,
+        progress * 100.0
+    );
 
     let gauge = Gauge::default()
         .block(Block::default().title("Progress").borders(Borders::ALL))
         .gauge_style(Style::default().fg(tui::style::Color::Green))
         .label(Span::raw(gauge_label))
-        .percent(progress);
+        .ratio(progress);
     gauge
 }
@@ -1,12 +1,32 @@
+use crate::histogram::LatencyHistogram;
+use crate::slo::{Apdex, ErrorBudget};
+use crate::window::Timeline;
+use netlink_wi::NlSocket;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
 use std::time::Duration;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Report {
     pub succeeded: i64,
     pub failed: i64,
+    /// requests given up on after --timeout, not counted in `failed`
+    pub timed_out: i64,
     pub total_requests: i64,
+    /// http status of the response, None when there was no response at all
+    pub status: Option<u16>,
+    /// failures below http, a breakdown of part of `failed`
+    pub transport_errors: TransportErrors,
+    /// where the time of the request went
+    pub phases: Phases,
     pub elapsed: u64,
-    pub transaction_rate: f64,
+    /// http headers and body written for the request
+    pub bytes_sent: u64,
+    /// http headers and body read back, 0 unless the whole response was read
+    pub bytes_received: u64,
     pub duration: Duration,
+    pub corrected_duration: Duration,
 }
 
 impl Report {
@@ -14,26 +34,226 @@ impl Report {
         Report {
             succeeded: 0,
             failed: 0,
+            timed_out: 0,
             total_requests: 0,
+            status: None,
+            transport_errors: TransportErrors::default(),
+            phases: Phases::default(),
             elapsed: 0,
-            transaction_rate: 0.0,
+            bytes_sent: 0,
+            bytes_received: 0,
             duration: Duration::new(0, 0),
+            corrected_duration: Duration::new(0, 0),
         }
     }
-    pub fn add_report(
-        &mut self,
-        succeed_count: i64,
-        failed_count: i64,
-        total_req_count: i64,
-        el: u64,
-    ) {
-        self.succeeded += succeed_count;
-        self.failed += failed_count;
-        self.total_requests += total_req_count;
-        self.elapsed = el;
+    pub fn add_report(&mut self, received: &Report) {
+        self.succeeded += received.succeeded;
+        self.failed += received.failed;
+        self.timed_out += received.timed_out;
+        self.transport_errors.add(&received.transport_errors);
+        self.total_requests += received.total_requests;
+        self.bytes_sent += received.bytes_sent;
+        self.bytes_received += received.bytes_received;
+        self.elapsed = received.elapsed;
     }
 }
 
+/// what the tower collected over a whole run, handed back once it is over
+pub struct RunResults {
+    /// every report added up
+    pub report: Report,
+    /// coordinated omission corrected latencies
+    pub latencies: LatencyHistogram,
+    /// latencies from when each request actually started, only with --show-uncorrected
+    pub uncorrected_latencies: Option<LatencyHistogram>,
+    /// only with --apdex-t
+    pub apdex: Option<Apdex>,
+    pub status_codes: StatusCodes,
+    /// only with --availability
+    pub error_budget: Option<ErrorBudget>,
+    /// the run second by second, warm-up included
+    pub timeline: Timeline,
+    /// bytes sent and received second by second, warm-up included
+    pub throughput: Throughput,
+    pub warmup: Duration,
+    /// from the end of warm-up until the tower stopped
+    pub elapsed: Duration,
+    /// false when the run was stopped before every request was in
+    pub completed: bool,
+}
+
+impl RunResults {
+    /// requests per second over the whole run
+    pub fn rps(&self) -> f64 {
+        rps(&self.report, self.elapsed)
+    }
+
+    /// failed and timed out requests over all requests, None before any request
+    pub fn error_rate(&self) -> Option<f64> {
+        let report = &self.report;
+        if report.total_requests == 0 {
+            None
+        } else {
+            Some((report.failed + report.timed_out) as f64 / report.total_requests as f64)
+        }
+    }
+}
+
+/// decides when the load generator stops sending requests
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum TestLength {
+    /// keep sending until this much time has passed since the start
+    Duration(Duration),
+    /// send exactly this many requests
+    Requests(u64),
+}
+
+/// breakdown of a request's duration. dns, connect and tls are only non zero for
+/// the request that opened a new connection
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Phases {
+    pub dns: Duration,
+    pub connect: Duration,
+    pub tls: Duration,
+    /// from sending the request, after any connection setup, to the response headers
+    pub ttfb: Duration,
+    /// reading the response body
+    pub body: Duration,
+}
+
+impl Phases {
+    /// (label, duration) of every phase, in the order they happen
+    pub fn named(&self) -> [(&'static str, Duration); 5] {
+        [
+            ("DNS", self.dns),
+            ("Connect", self.connect),
+            ("TLS", self.tls),
+            ("TTFB", self.ttfb),
+            ("Body", self.body),
+        ]
+    }
+}
+
+/// why a request failed to get a complete response
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransportError {
+    Dns,
+    ConnectionRefused,
+    ConnectionReset,
+    Tls,
+    /// a timeout raised by the http client itself, e.g. --connect-timeout
+    Timeout,
+    BodyRead,
+    Other,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TransportErrors {
+    pub dns: i64,
+    pub connection_refused: i64,
+    pub connection_reset: i64,
+    pub tls: i64,
+    pub timeout: i64,
+    pub body_read: i64,
+    pub other: i64,
+}
+
+impl TransportErrors {
+    pub fn record(&mut self, err: TransportError) {
+        let counter = match err {
+            TransportError::Dns => &mut self.dns,
+            TransportError::ConnectionRefused => &mut self.connection_refused,
+            TransportError::ConnectionReset => &mut self.connection_reset,
+            TransportError::Tls => &mut self.tls,
+            TransportError::Timeout => &mut self.timeout,
+            TransportError::BodyRead => &mut self.body_read,
+            TransportError::Other => &mut self.other,
+        };
+        *counter += 1;
+    }
+
+    pub fn add(&mut self, other: &TransportErrors) {
+        self.dns += other.dns;
+        self.connection_refused += other.connection_refused;
+        self.connection_reset += other.connection_reset;
+        self.tls += other.tls;
+        self.timeout += other.timeout;
+        self.body_read += other.body_read;
+        self.other += other.other;
+    }
+
+    /// the field name of the first category with an error in it, for the report of a
+    /// single request that's the one error it ran into
+    pub fn kind(&self) -> Option<&'static str> {
+        [
+            ("dns", self.dns),
+            ("connection_refused", self.connection_refused),
+            ("connection_reset", self.connection_reset),
+            ("tls", self.tls),
+            ("timeout", self.timeout),
+            ("body_read", self.body_read),
+            ("other", self.other),
+        ]
+        .iter()
+        .find(|(_, count)| *count > 0)
+        .map(|(name, _)| *name)
+    }
+
+    /// (label, count) of every category, in display order
+    pub fn counts(&self) -> [(&'static str, i64); 7] {
+        [
+            ("DNS Resolution", self.dns),
+            ("Connection Refused", self.connection_refused),
+            ("Connection Reset", self.connection_reset),
+            ("TLS Handshake", self.tls),
+            ("Timeout", self.timeout),
+            ("Body Read", self.body_read),
+            ("Other", self.other),
+        ]
+    }
+}
+
+/// how many responses came back with each status code
+#[derive(Serialize, Deserialize)]
+pub struct StatusCodes {
+    by_code: BTreeMap<u16, u64>,
+}
+
+impl StatusCodes {
+    pub fn new() -> Self {
+        StatusCodes {
+            by_code: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, status: u16) {
+        *self.by_code.entry(status).or_insert(0) += 1;
+    }
+
+    pub fn merge(&mut self, other: &StatusCodes) {
+        for (code, count) in other.by_code() {
+            *self.by_code.entry(code).or_insert(0) += count;
+        }
+    }
+
+    /// (code, count) in ascending code order
+    pub fn by_code(&self) -> impl Iterator<Item = (u16, u64)> + '_ {
+        self.by_code.iter().map(|(&code, &count)| (code, count))
+    }
+
+    /// counts for the 2xx, 3xx, 4xx and 5xx classes, in that order
+    pub fn by_class(&self) -> [(&'static str, u64); 4] {
+        let mut classes = [("2xx", 0), ("3xx", 0), ("4xx", 0), ("5xx", 0)];
+        for (code, count) in self.by_code() {
+            if (200..600).contains(&code) {
+                classes[(code / 100 - 2) as usize].1 += count;
+            }
+        }
+        classes
+    }
+}
+
+#[derive(Serialize)]
 pub struct MachineDetails {
     pub ssid: String,
     pub tx_bitrate: f32,
@@ -52,4 +272,102 @@ impl MachineDetails {
             frequency: 0,
         }
     }
+
+    /// reads the wireless link details, all zeros when there is no wireless
+    /// interface or nl80211 can't be talked to
+    pub fn probe() -> Self {
+        MachineDetails::query().unwrap_or_else(|_| MachineDetails::new())
+    }
+
+    fn query() -> Result<Self, Box<dyn Error>> {
+        let mut machine_details = MachineDetails::new();
+
+        let socket = NlSocket::connect()?;
+        let interfaces = socket.list_interfaces()?;
+        for interface in interfaces {
+            let interface = interface?;
+            let stations = socket.list_stations(interface.interface_index)?;
+            for station in stations {
+                let station = station?;
+                machine_details.avg_signal = station.average_signal.unwrap_or_default();
+                machine_details.rx_bitrate = match station.rx_bitrate {
+                    Some(v) => v.bitrate as f32 * 100.0 / 1000_f32,
+                    None => 0.0,
+                };
+                machine_details.tx_bitrate = match station.tx_bitrate {
+                    Some(v) => v.bitrate as f32 * 100.0 / 1000_f32,
+                    None => 0.0,
+                };
+                machine_details.frequency = interface.frequency.unwrap_or_default();
+                machine_details.ssid = match interface.ssid {
+                    Some(ref v) => v.to_string(),
+                    None => 0.to_string(),
+                };
+            }
+        }
+
+        Ok(machine_details)
+    }
+}
+
+/// http bytes moved during each second of the test
+pub struct Throughput {
+    // (sent, received) indexed by whole seconds since the start
+    per_second: Vec<(u64, u64)>,
+}
+
+impl Throughput {
+    pub fn new() -> Self {
+        Throughput {
+            per_second: Vec::new(),
+        }
+    }
+
+    /// adds a request's bytes to the second, since the start, it finished in
+    pub fn record(&mut self, at: Duration, sent: u64, received: u64) {
+        let second = at.as_secs() as usize;
+        if self.per_second.len() <= second {
+            self.per_second.resize(second + 1, (0, 0));
+        }
+        self.per_second[second].0 += sent;
+        self.per_second[second].1 += received;
+    }
+
+    /// (sent, received) in MB/s over the last whole second before `at`
+    pub fn last_second(&self, at: Duration) -> (f64, f64) {
+        let second = match (at.as_secs() as usize).checked_sub(1) {
+            Some(second) => second,
+            None => return (0.0, 0.0),
+        };
+        let (sent, received) = self.per_second.get(second).copied().unwrap_or((0, 0));
+        (megabytes(sent), megabytes(received))
+    }
+
+    /// (sent, received) bytes of every second so far, oldest first
+    pub fn per_second(&self) -> &[(u64, u64)] {
+        &self.per_second
+    }
+}
+
+/// requests per second of `report` over `elapsed`, 0 if no time passed
+pub fn rps(report: &Report, elapsed: Duration) -> f64 {
+    let elapsed = elapsed.as_secs_f64();
+    if elapsed > 0.0 {
+        report.total_requests as f64 / elapsed
+    } else {
+        0.0
+    }
+}
+
+pub fn megabytes(bytes: u64) -> f64 {
+    bytes as f64 / 1_000_000.0
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// e.g. `4.120ms`
+pub fn format_latency(latency: Duration) -> String {
+    format!("{:.3}ms", millis(latency))
 }
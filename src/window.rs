@@ -0,0 +1,240 @@
+use crate::histogram::LatencyHistogram;
+use crate::Report;
+use std::time::Duration;
+
+/// what happened during one second of the test
+pub struct Second {
+    pub requests: u64,
+    /// failed and timed out requests
+    pub errors: u64,
+    // the whole distribution, only kept while the second is inside the window
+    latencies: Option<LatencyHistogram>,
+    /// the configured percentiles of the second, filled in once it is over
+    pub percentiles: Vec<(f64, Duration)>,
+}
+
+/// stats over the most recent stretch of the test
+pub struct WindowStats {
+    pub requests_per_sec: f64,
+    /// share of the requests that failed or timed out, 0 to 1
+    pub error_rate: f64,
+    pub latencies: LatencyHistogram,
+}
+
+/// the test cut into one second buckets, so recent numbers can be told apart from
+/// the cumulative ones
+pub struct Timeline {
+    seconds: Vec<Second>,
+    window: Duration,
+    significant_digits: u8,
+    percentiles: Vec<f64>,
+}
+
+impl Timeline {
+    pub fn new(window: Duration, significant_digits: u8, percentiles: Vec<f64>) -> Self {
+        Timeline {
+            seconds: Vec::new(),
+            window,
+            significant_digits,
+            percentiles,
+        }
+    }
+
+    /// whole seconds the window spans, at least one
+    fn window_secs(&self) -> u64 {
+        (self.window.as_secs_f64().ceil() as u64).max(1)
+    }
+
+    /// adds a request that finished `at` into the test
+    pub fn record(&mut self, at: Duration, report: &Report) {
+        let second = self.second_at(at);
+        second.requests += report.total_requests as u64;
+        second.errors += (report.failed + report.timed_out) as u64;
+        if let Some(latencies) = second.latencies.as_mut() {
+            latencies.record(report.corrected_duration);
+        }
+    }
+
+    /// adds several requests at once, all of them counted in the second `at` falls in
+    pub fn merge(&mut self, at: Duration, report: &Report, latencies: &LatencyHistogram) {
+        let second = self.second_at(at);
+        second.requests += report.total_requests as u64;
+        second.errors += (report.failed + report.timed_out) as u64;
+        if let Some(second_latencies) = second.latencies.as_mut() {
+            second_latencies.merge(latencies);
+        }
+    }
+
+    fn second_at(&mut self, at: Duration) -> &mut Second {
+        self.advance(at);
+        self.seconds
+            .last_mut()
+            .expect("advance adds the current second")
+    }
+
+    /// opens buckets up to the second `now` falls in, even ones nothing happened in,
+    /// and wraps up the ones that are over
+    pub fn advance(&mut self, now: Duration) {
+        let current = now.as_secs() as usize;
+        while self.seconds.len() <= current {
+            let started = self.seconds.len();
+            if let Some(finished) = self.seconds.last_mut() {
+                if let Some(latencies) = finished.latencies.as_ref() {
+                    finished.percentiles = self
+                        .percentiles
+                        .iter()
+                        .copied()
+                        .zip(latencies.percentiles(&self.percentiles))
+                        .collect();
+                }
+            }
+            // the second just leaving the window won't be merged again. with a 1s window
+            // that is the one finished above, so its percentiles have to be in first
+            let window_secs = self.window_secs() as usize;
+            if started >= window_secs {
+                self.seconds[started - window_secs].latencies = None;
+            }
+
+            self.seconds.push(Second {
+                requests: 0,
+                errors: 0,
+                latencies: Some(
+                    LatencyHistogram::new(self.significant_digits)
+                        .expect("significant digits are checked when parsing the cli"),
+                ),
+                percentiles: Vec::new(),
+            });
+        }
+    }
+
+    /// every second that is over, oldest first
+    pub fn finished_seconds(&self) -> &[Second] {
+        &self.seconds[..self.seconds.len().saturating_sub(1)]
+    }
+
+    /// stats over the window ending `now`, the second in progress included
+    pub fn window(&mut self, now: Duration) -> WindowStats {
+        self.advance(now);
+        let current = now.as_secs();
+        let first = (current + 1).saturating_sub(self.window_secs());
+        // shorter than the window early on in the test
+        let covered = (now - Duration::from_secs(first)).as_secs_f64();
+
+        let mut latencies = LatencyHistogram::new(self.significant_digits)
+            .expect("significant digits are checked when parsing the cli");
+        let (mut requests, mut errors) = (0, 0);
+        for second in &self.seconds[first as usize..] {
+            requests += second.requests;
+            errors += second.errors;
+            if let Some(second_latencies) = second.latencies.as_ref() {
+                latencies.merge(second_latencies);
+            }
+        }
+
+        WindowStats {
+            requests_per_sec: if covered > 0.0 {
+                requests as f64 / covered
+            } else {
+                0.0
+            },
+            error_rate: if requests > 0 {
+                errors as f64 / requests as f64
+            } else {
+                0.0
+            },
+            latencies,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // latencies below 2048µs are kept exactly at 3 significant digits
+    fn us(micros: u64) -> Duration {
+        Duration::from_micros(micros)
+    }
+
+    fn report(latency: Duration, failed: bool) -> Report {
+        let mut report = Report::new();
+        report.total_requests = 1;
+        if failed {
+            report.failed = 1;
+        } else {
+            report.succeeded = 1;
+        }
+        report.corrected_duration = latency;
+        report
+    }
+
+    fn new_timeline(window: Duration) -> Timeline {
+        Timeline::new(window, 3, vec![50.0, 99.0])
+    }
+
+    #[test]
+    fn one_second_window_only_holds_the_current_second() {
+        let mut timeline = new_timeline(ms(1000));
+        timeline.record(ms(200), &report(us(100), false));
+        timeline.record(ms(700), &report(us(200), true));
+        timeline.record(ms(1500), &report(us(300), false));
+
+        let window = timeline.window(ms(1600));
+        assert!((window.requests_per_sec - 1.0 / 0.6).abs() < 1e-9);
+        assert_eq!(window.error_rate, 0.0);
+        assert_eq!(window.latencies.summary(&[]).count, 1);
+        assert_eq!(window.latencies.percentile(50.0), us(300));
+
+        // the first second left the window but kept its percentiles
+        let finished = timeline.finished_seconds();
+        assert_eq!(finished.len(), 1);
+        assert_eq!((finished[0].requests, finished[0].errors), (2, 1));
+        assert_eq!(finished[0].percentiles[0], (50.0, us(150)));
+        assert!(finished[0].latencies.is_none());
+    }
+
+    #[test]
+    fn fractional_window_covers_the_seconds_it_touches() {
+        // 2.5s rounds up to the last 3 seconds, the current one included
+        let mut timeline = new_timeline(ms(2500));
+        for at in [500, 1500, 2500, 3500] {
+            timeline.record(ms(at), &report(us(at / 10), false));
+        }
+
+        let window = timeline.window(ms(3600));
+        assert!((window.requests_per_sec - 3.0 / 2.6).abs() < 1e-9);
+        assert_eq!(window.latencies.summary(&[]).count, 3);
+        assert_eq!(window.latencies.percentile(0.0), us(150));
+
+        // early on the window is as long as the test so far
+        let mut early = new_timeline(ms(500));
+        early.record(ms(100), &report(ms(1), false));
+        assert!((early.window(ms(500)).requests_per_sec - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn quiet_seconds_are_filled_in_too() {
+        let mut timeline = new_timeline(ms(1000));
+        timeline.record(ms(500), &report(us(100), false));
+
+        let window = timeline.window(ms(5500));
+        assert_eq!(window.requests_per_sec, 0.0);
+        assert_eq!(window.error_rate, 0.0);
+        assert_eq!(window.latencies.summary(&[]).count, 0);
+
+        let finished = timeline.finished_seconds();
+        assert_eq!(finished.len(), 5);
+        assert_eq!(finished[0].requests, 1);
+        for second in finished {
+            // every finished second has all its percentiles, quiet or evicted
+            assert_eq!(second.percentiles.len(), 2);
+            assert!(second.latencies.is_none());
+        }
+        assert_eq!(finished[4].requests, 0);
+        assert_eq!(finished[4].percentiles[1], (99.0, Duration::ZERO));
+    }
+}